@@ -4,6 +4,7 @@ extern crate clap;
 extern crate log;
 extern crate env_logger;
 extern crate open;
+extern crate toml;
 
 // Dependencies for the Watch feature
 #[cfg(feature = "watch")]
@@ -15,11 +16,9 @@ extern crate crossbeam;
 
 // Dependencies for the Serve feature
 #[cfg(feature = "serve")]
-extern crate iron;
-#[cfg(feature = "serve")]
-extern crate staticfile;
-#[cfg(feature = "serve")]
 extern crate ws;
+#[cfg(test)]
+extern crate tempdir;
 
 use std::env;
 use std::error::Error;
@@ -36,9 +35,24 @@ use notify::Watcher;
 use std::time::Duration;
 #[cfg(feature = "watch")]
 use std::sync::mpsc::channel;
+#[cfg(feature = "serve")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "serve")]
+use std::fs::File;
+#[cfg(feature = "serve")]
+use std::io::Read;
+#[cfg(feature = "serve")]
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(feature = "serve")]
+use std::str;
 
 
-use mdbook::MDBook;
+use mdbook::{MDBook, ConfigError};
+use mdbook::book::bookconfig::parse_override;
+#[cfg(feature = "serve")]
+use mdbook::book::LiveReload;
+#[cfg(feature = "serve")]
+use mdbook::book::livereload::Message;
 
 const NAME: &'static str = "mdbook";
 
@@ -63,21 +77,22 @@ fn main() {
                         .about("Build the book from the markdown files")
                         .arg_from_usage("-o, --open 'Open the compiled book in a web browser'")
                         .arg_from_usage("-d, --dest-dir=[dest-dir] 'The output directory for your book{n}(Defaults to ./book when omitted)'")
+                        .arg_from_usage("-c, --config=[config]... 'Overrides a configuration key, e.g. book.title=\"My book\"{n}(Can be given several times)'")
                         .arg_from_usage("--no-create 'Will not create non-existent files linked from SUMMARY.md'")
                         .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when omitted)'"))
                     .subcommand(SubCommand::with_name("watch")
                         .about("Watch the files for changes")
                         .arg_from_usage("-o, --open 'Open the compiled book in a web browser'")
                         .arg_from_usage("-d, --dest-dir=[dest-dir] 'The output directory for your book{n}(Defaults to ./book when omitted)'")
+                        .arg_from_usage("-c, --config=[config]... 'Overrides a configuration key, e.g. book.title=\"My book\"{n}(Can be given several times)'")
                         .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when omitted)'"))
                     .subcommand(SubCommand::with_name("serve")
                         .about("Serve the book at http://localhost:3000. Rebuild and reload on change.")
                         .arg_from_usage("[dir] 'A directory for your book{n}(Defaults to Current Directory when omitted)'")
                         .arg_from_usage("-d, --dest-dir=[dest-dir] 'The output directory for your book{n}(Defaults to ./book when omitted)'")
+                        .arg_from_usage("-c, --config=[config]... 'Overrides a configuration key, e.g. book.title=\"My book\"{n}(Can be given several times)'")
                         .arg_from_usage("-p, --port=[port] 'Use another port{n}(Defaults to 3000)'")
-                        .arg_from_usage("-w, --websocket-port=[ws-port] 'Use another port for the websocket connection (livereload){n}(Defaults to 3001)'")
                         .arg_from_usage("-i, --interface=[interface] 'Interface to listen on{n}(Defaults to localhost)'")
-                        .arg_from_usage("-a, --address=[address] 'Address that the browser can reach the websocket server from{n}(Defaults to the interface address)'")
                         .arg_from_usage("-o, --open 'Open the book server in a web browser'"))
                     .subcommand(SubCommand::with_name("test")
                         .about("Test that code samples compile"))
@@ -96,12 +111,48 @@ fn main() {
     };
 
     if let Err(e) = res {
-        writeln!(&mut io::stderr(), "An error occured:\n{}", e).ok();
+        match e.downcast_ref::<mdbook::errors::Error>() {
+            Some(&mdbook::errors::Error::Config(ref e)) => print_config_error(e),
+            _ => print_error(&*e),
+        }
         ::std::process::exit(101);
     }
 }
 
 
+// Prints an error followed by its causes
+fn print_error(e: &Error) {
+    let mut stderr = io::stderr();
+    writeln!(&mut stderr, "An error occured:\n{}", e).ok();
+
+    let mut cause = e.cause();
+    while let Some(e) = cause {
+        writeln!(&mut stderr, "\nCaused by:\n{}", e).ok();
+        cause = e.cause();
+    }
+}
+
+
+// Prints a configuration error with the offending line, like the compiler does
+fn print_config_error(e: &ConfigError) {
+    let mut stderr = io::stderr();
+    writeln!(&mut stderr, "error: {}", e.message).ok();
+
+    match (e.position, e.source_line.as_ref()) {
+        (Some((line, column)), Some(source)) => {
+            let gutter = " ".repeat(line.to_string().len());
+            writeln!(&mut stderr, "{}--> {}:{}:{}", gutter, e.path.display(), line, column).ok();
+            writeln!(&mut stderr, "{} |", gutter).ok();
+            writeln!(&mut stderr, "{} | {}", line, source).ok();
+            writeln!(&mut stderr, "{} | {}^", gutter, " ".repeat(column.saturating_sub(1))).ok();
+        },
+        _ => {
+            writeln!(&mut stderr, " --> {}", e.path.display()).ok();
+        },
+    }
+}
+
+
 // Simple function that user confirmation
 fn confirm() -> bool {
     io::stdout().flush().unwrap();
@@ -154,7 +205,7 @@ fn init(args: &ArgMatches) -> Result<(), Box<Error>> {
         println!("\nDo you want a .gitignore to be created? (y/n)");
 
         if confirm() {
-            book.create_gitignore();
+            try!(book.create_gitignore());
             println!("\n.gitignore created.");
         }
     }
@@ -167,13 +218,7 @@ fn init(args: &ArgMatches) -> Result<(), Box<Error>> {
 
 // Build command implementation
 fn build(args: &ArgMatches) -> Result<(), Box<Error>> {
-    let book_dir = get_book_dir(args);
-    let book = MDBook::new(&book_dir).read_config();
-
-    let mut book = match args.value_of("dest-dir") {
-        Some(dest_dir) => book.set_dest(Path::new(dest_dir)),
-        None => book
-    };
+    let mut book = try!(load_book(args));
 
     if args.is_present("no-create") {
         book.create_missing = false;
@@ -182,7 +227,7 @@ fn build(args: &ArgMatches) -> Result<(), Box<Error>> {
     try!(book.build());
 
     if args.is_present("open") {
-        open(book.get_dest().join("index.html"));
+        open(try!(html_dest(&book)).join("index.html"));
     }
 
     Ok(())
@@ -192,23 +237,19 @@ fn build(args: &ArgMatches) -> Result<(), Box<Error>> {
 // Watch command implementation
 #[cfg(feature = "watch")]
 fn watch(args: &ArgMatches) -> Result<(), Box<Error>> {
-    let book_dir = get_book_dir(args);
-    let book = MDBook::new(&book_dir).read_config();
-
-    let mut book = match args.value_of("dest-dir") {
-        Some(dest_dir) => book.set_dest(Path::new(dest_dir)),
-        None => book
-    };
+    let mut book = try!(load_book(args));
+    book.set_incremental(true);
 
     if args.is_present("open") {
+        let dest = try!(html_dest(&book));
         try!(book.build());
-        open(book.get_dest().join("index.html"));
+        open(dest.join("index.html"));
     }
 
     trigger_on_change(&mut book, |path, book| {
         println!("File changed: {:?}\nBuilding book...\n", path);
         if let Err(e) = book.build() {
-            println!("Error while building: {:?}", e);
+            print_error(&e);
         }
         println!("");
     });
@@ -220,59 +261,66 @@ fn watch(args: &ArgMatches) -> Result<(), Box<Error>> {
 // Watch command implementation
 #[cfg(feature = "serve")]
 fn serve(args: &ArgMatches) -> Result<(), Box<Error>> {
-    const RELOAD_COMMAND: &'static str = "reload";
-
-    let book_dir = get_book_dir(args);
-    let book = MDBook::new(&book_dir).read_config();
-
-    let mut book = match args.value_of("dest-dir") {
-        Some(dest_dir) => book.set_dest(Path::new(dest_dir)),
-        None => book
-    };
+    let mut book = try!(load_book(args));
+    book.set_incremental(true);
 
     let port = args.value_of("port").unwrap_or("3000");
-    let ws_port = args.value_of("ws-port").unwrap_or("3001");
     let interface = args.value_of("interface").unwrap_or("localhost");
-    let public_address = args.value_of("address").unwrap_or(interface);
     let open_browser = args.is_present("open");
 
     let address = format!("{}:{}", interface, port);
-    let ws_address = format!("{}:{}", interface, ws_port);
-
-    book.set_livereload(format!(r#"
-        <script type="text/javascript">
-            var socket = new WebSocket("ws://{}:{}");
-            socket.onmessage = function (event) {{
-                if (event.data === "{}") {{
-                    socket.close();
-                    location.reload(true); // force reload from server (not from cache)
-                }}
-            }};
-
-            window.onbeforeunload = function() {{
-                socket.close();
-            }}
-        </script>
-    "#, public_address, ws_port, RELOAD_COMMAND).to_owned());
-
-    try!(book.build());
-
-    let staticfile = staticfile::Static::new(book.get_dest());
-    let iron = iron::Iron::new(staticfile);
-    let _iron = iron.http(&*address).unwrap();
-
-    let ws_server = ws::WebSocket::new(|_| {
-        |_| {
-            Ok(())
+    let root = try!(html_dest(&book));
+
+    // The pages connect to the websocket on the server they come from, so that the book works
+    // behind a proxy
+    book.set_livereload(LiveReload::new(LIVERELOAD_PATH));
+
+    // The error of the last build if it failed, for the pages opened since
+    let last_error = Arc::new(Mutex::new(None));
+
+    // The pages are served over plain HTTP. The live reload websocket is handled by a ws server on a
+    // local port, its connections are passed through, so that the pages only need the one address.
+    // Bound before the server threads start, so that an address already in use is reported
+    let listener = try!(TcpListener::bind(&*address));
+    let ws_address = try!(TcpListener::bind("127.0.0.1:0").and_then(|l| l.local_addr()));
+
+    let server_last_error = last_error.clone();
+    let server = try!(ws::WebSocket::new(move |out| {
+        LiveReloadConnection {
+            out: out,
+            last_error: server_last_error.clone(),
         }
-    }).unwrap();
+    }));
+    let server = try!(server.bind(ws_address));
+    let broadcaster = server.broadcaster();
 
-    let broadcaster = ws_server.broadcaster();
+    std::thread::spawn(move || {
+        if let Err(e) = server.run() {
+            println!("Error while serving the live reload: {}", e);
+        }
+    });
 
     std::thread::spawn(move || {
-        ws_server.listen(&*ws_address).unwrap();
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("Error while serving the book: {}", e);
+                    continue;
+                },
+            };
+            let root = root.clone();
+            // Errors of a single connection, like a page closed during a request, only end it
+            std::thread::spawn(move || handle_connection(stream, &root, ws_address));
+        }
     });
 
+    // A book that doesn't build yet is served anyway, its error shows in the pages until it is fixed
+    if let Err(e) = book.build() {
+        print_error(&e);
+        *last_error.lock().unwrap() = Some(Message::error(&e).to_json());
+    }
+
     println!("\nServing on {}", address);
 
     if open_browser {
@@ -282,8 +330,23 @@ fn serve(args: &ArgMatches) -> Result<(), Box<Error>> {
     trigger_on_change(&mut book, move |path, book| {
         println!("File changed: {:?}\nBuilding book...\n", path);
         match book.build() {
-            Err(e) => println!("Error while building: {:?}", e),
-            _ => broadcaster.send(RELOAD_COMMAND).unwrap(),
+            // Show the error in the pages too, the terminal may not be visible
+            Err(e) => {
+                print_error(&e);
+                let message = Message::error(&e).to_json();
+                if let Err(e) = broadcaster.send(&*message) {
+                    println!("Error while sending the build error to the pages: {}", e);
+                }
+                *last_error.lock().unwrap() = Some(message);
+            },
+            // Tell the pages what changed, so that only the affected ones reload
+            _ => {
+                *last_error.lock().unwrap() = None;
+                let message = Message::changed(&book.get_renderer_dest("html"), &book.changed_files());
+                if let Err(e) = broadcaster.send(message.to_json()) {
+                    println!("Error while telling the pages to reload: {}", e);
+                }
+            },
         }
         println!();
     });
@@ -292,14 +355,300 @@ fn serve(args: &ArgMatches) -> Result<(), Box<Error>> {
 }
 
 
+// Where the pages connect to the live reload websocket
+#[cfg(feature = "serve")]
+const LIVERELOAD_PATH: &'static str = "/__livereload";
+
+// A page connected to the live reload websocket
+#[cfg(feature = "serve")]
+struct LiveReloadConnection {
+    out: ws::Sender,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+#[cfg(feature = "serve")]
+impl ws::Handler for LiveReloadConnection {
+    // Pages opened after a failed build show its error right away
+    fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
+        match *self.last_error.lock().unwrap() {
+            Some(ref message) => self.out.send(&**message),
+            None => Ok(()),
+        }
+    }
+}
+
+// The start of an HTTP request, the only part the server looks at
+#[cfg(feature = "serve")]
+#[derive(Debug, PartialEq)]
+struct Request<'a> {
+    method: &'a str,
+    resource: &'a str,
+    websocket: bool,
+}
+
+#[cfg(feature = "serve")]
+impl<'a> Request<'a> {
+    // Parses the request line and headers of `head`, `None` if it isn't an HTTP/1 request
+    fn parse(head: &'a str) -> Option<Request<'a>> {
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or("").split(' ');
+        let (method, resource, version) = match (request_line.next(), request_line.next(), request_line.next()) {
+            (Some(method), Some(resource), Some(version)) => (method, resource, version),
+            _ => return None,
+        };
+        if method.is_empty() || !resource.starts_with('/') || !version.starts_with("HTTP/1.") {
+            return None;
+        }
+
+        let websocket = lines.filter_map(|line| {
+                                 let mut header = line.splitn(2, ':');
+                                 match (header.next(), header.next()) {
+                                     (Some(name), Some(value)) => Some((name.trim(), value.trim())),
+                                     _ => None,
+                                 }
+                             })
+                             .any(|(name, value)| {
+                                 name.eq_ignore_ascii_case("upgrade") && value.eq_ignore_ascii_case("websocket")
+                             });
+
+        Some(Request {
+            method: method,
+            resource: resource,
+            websocket: websocket,
+        })
+    }
+}
+
+// Answers the request on `stream`: a file of the book at `root`, or the live reload websocket,
+// passed through to the ws server at `ws_address`. Every connection serves a single request.
+#[cfg(feature = "serve")]
+fn handle_connection(mut stream: TcpStream, root: &Path, ws_address: SocketAddr) -> io::Result<()> {
+    let head = try!(read_head(&mut stream));
+    let end = head.windows(4).position(|w| w == b"\r\n\r\n").unwrap_or(head.len());
+
+    let request = match str::from_utf8(&head[..end]).ok().and_then(Request::parse) {
+        Some(request) => request,
+        None => return write_status(&mut stream, 400, "Bad Request"),
+    };
+
+    if request.websocket && request.resource == LIVERELOAD_PATH {
+        return pass_through(stream, &head, ws_address);
+    }
+
+    match request.method {
+        "GET" => send_file(&mut stream, root, request.resource, true),
+        "HEAD" => send_file(&mut stream, root, request.resource, false),
+        _ => write_status(&mut stream, 501, "Not Implemented"),
+    }
+}
+
+// The bytes read from `stream` up to the end of the request headers, and maybe some after
+#[cfg(feature = "serve")]
+fn read_head(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut head = vec![];
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > 16 * 1024 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request headers too long"));
+        }
+        let read = try!(stream.read(&mut buffer));
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete request"));
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Ok(head)
+}
+
+// Copies the bytes between the page and the ws server until one of them closes the connection
+#[cfg(feature = "serve")]
+fn pass_through(mut stream: TcpStream, head: &[u8], ws_address: SocketAddr) -> io::Result<()> {
+    let mut server = try!(TcpStream::connect(ws_address));
+    try!(server.write_all(head));
+
+    let mut from_page = try!(stream.try_clone());
+    let mut to_server = try!(server.try_clone());
+    let forward = std::thread::spawn(move || {
+        let _ = io::copy(&mut from_page, &mut to_server);
+        let _ = to_server.shutdown(Shutdown::Write);
+    });
+
+    let _ = io::copy(&mut server, &mut stream);
+    let _ = stream.shutdown(Shutdown::Both);
+    let _ = forward.join();
+    Ok(())
+}
+
+// Sends the file for `resource`, or the 404 page of the book. The file is streamed, not read in
+// memory, and `Range` headers are ignored: the whole file is sent, as `Accept-Ranges: none` tells.
+#[cfg(feature = "serve")]
+fn send_file(stream: &mut TcpStream, root: &Path, resource: &str, with_body: bool) -> io::Result<()> {
+    let (status, reason, path) = match resolve(root, resource) {
+        Some(path) => (200, "OK", path),
+        None => (404, "Not Found", root.join("404.html")),
+    };
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return write_status(stream, status, reason),
+    };
+    let length = try!(file.metadata()).len();
+
+    try!(stream.write_all(response_head(status, reason, mime_type(&path), length).as_bytes()));
+    if with_body {
+        try!(io::copy(&mut file.by_ref().take(length), stream));
+    }
+    stream.flush()
+}
+
+// A response with only its status, like when there is no 404 page
+#[cfg(feature = "serve")]
+fn write_status(stream: &mut TcpStream, status: u16, reason: &str) -> io::Result<()> {
+    let head = response_head(status, reason, "text/plain; charset=utf-8", reason.len() as u64);
+    try!(stream.write_all(head.as_bytes()));
+    try!(stream.write_all(reason.as_bytes()));
+    stream.flush()
+}
+
+// Nothing is cached by the browser, the book changes all the time
+#[cfg(feature = "serve")]
+fn response_head(status: u16, reason: &str, content_type: &str, length: u64) -> String {
+    format!("HTTP/1.1 {} {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             Accept-Ranges: none\r\n\
+             Cache-Control: no-store\r\n\
+             Connection: close\r\n\r\n",
+            status,
+            reason,
+            content_type,
+            length)
+}
+
+// The file at the path of `resource` in `root`. Directories are served by their `index.html` and
+// pages may be requested without their `.html` extension. Paths leaving `root` are not found.
+#[cfg(feature = "serve")]
+fn resolve(root: &Path, resource: &str) -> Option<PathBuf> {
+    let path = resource.split(|c| c == '?' || c == '#').next().unwrap_or("");
+    let path = match percent_decode(path) {
+        Some(path) => path,
+        None => return None,
+    };
+
+    let mut file = root.to_path_buf();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => return None,
+            part if part.contains('\\') || part.contains(':') => return None,
+            part => file.push(part),
+        }
+    }
+
+    if file.is_dir() {
+        file.push("index.html");
+    }
+    if file.is_file() {
+        return Some(file);
+    }
+
+    let mut page = file.into_os_string();
+    page.push(".html");
+    let page = PathBuf::from(page);
+    if page.is_file() { Some(page) } else { None }
+}
+
+#[cfg(feature = "serve")]
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if i + 3 > bytes.len() {
+                return None;
+            }
+            let hex = String::from_utf8_lossy(&bytes[i + 1..i + 3]);
+            match u8::from_str_radix(&hex, 16) {
+                Ok(byte) => decoded.push(byte),
+                Err(_) => return None,
+            }
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(feature = "serve")]
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+                        .and_then(|ext| ext.to_str())
+                        .map(|ext| ext.to_lowercase())
+                        .unwrap_or_default();
+
+    match &*extension {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "application/javascript; charset=utf-8",
+        "json" => "application/json",
+        "md" | "txt" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        "pdf" => "application/pdf",
+        "epub" => "application/epub+zip",
+        _ => "application/octet-stream",
+    }
+}
+
+
 fn test(args: &ArgMatches) -> Result<(), Box<Error>> {
-    let book_dir = get_book_dir(args);
-    let mut book = MDBook::new(&book_dir).read_config();
+    let mut book = try!(load_book(args));
 
     book.test()
 }
 
 
+// Loads the book and its configuration, with the `--dest-dir` and `--config` overrides of the
+// command line
+fn load_book(args: &ArgMatches) -> Result<MDBook, Box<Error>> {
+    let book_dir = get_book_dir(args);
+    let mut book = MDBook::new(&book_dir);
+
+    if let Some(dest_dir) = args.value_of("dest-dir") {
+        book = book.set_config_override("build.dest", toml::Value::String(dest_dir.to_owned()));
+    }
+
+    if let Some(overrides) = args.values_of("config") {
+        for arg in overrides {
+            let (key, value) = try!(parse_override(arg));
+            book = book.set_config_override(&key, value);
+        }
+    }
+
+    Ok(try!(book.read_config()))
+}
+
+
+// The output directory of the html renderer, the only output that can be opened or served
+fn html_dest(book: &MDBook) -> Result<PathBuf, Box<Error>> {
+    if !book.has_renderer("html") {
+        return Err("The book has no html output to open or serve, add an [output.html] table to book.toml".into());
+    }
+    Ok(book.get_renderer_dest("html"))
+}
+
+
 fn get_book_dir(args: &ArgMatches) -> PathBuf {
     if let Some(dir) = args.value_of("dir") {
         // Check if path is relative from current dir, or absolute...
@@ -376,3 +725,162 @@ fn trigger_on_change<F>(book: &mut MDBook, closure: F) -> ()
         }
     }
 }
+
+
+#[cfg(all(test, feature = "serve"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use tempdir::TempDir;
+
+    // A book output next to a file that must stay out of reach
+    fn site() -> (TempDir, PathBuf) {
+        let tmp = TempDir::new("mdbook").unwrap();
+        let root = tmp.path().join("book");
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::File::create(tmp.path().join("secret.html")).unwrap();
+        for page in &["index.html", "chapter.html", "with space.html", "nested/index.html", "404.html"] {
+            write!(fs::File::create(root.join(page)).unwrap(), "page {}", page).unwrap();
+        }
+        (tmp, root)
+    }
+
+    // Sends `request` to a connection served from `root` and returns the whole response
+    fn respond(root: &Path, request: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut page = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let root = root.to_path_buf();
+        let server = std::thread::spawn(move || handle_connection(stream, &root, "127.0.0.1:1".parse().unwrap()));
+        page.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        page.read_to_string(&mut response).unwrap();
+        server.join().unwrap().unwrap();
+        response
+    }
+
+    #[test]
+    fn percent_decode_decodes_escapes() {
+        assert_eq!(percent_decode("with%20space.html"), Some("with space.html".to_owned()));
+        assert_eq!(percent_decode("%2e%2E/x"), Some("../x".to_owned()));
+        assert_eq!(percent_decode("%C3%A9"), Some("é".to_owned()));
+        assert_eq!(percent_decode("plain"), Some("plain".to_owned()));
+    }
+
+    #[test]
+    fn percent_decode_rejects_invalid_escapes() {
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn resolve_finds_pages_and_indexes() {
+        let (_tmp, root) = site();
+
+        assert_eq!(resolve(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(resolve(&root, "/chapter.html"), Some(root.join("chapter.html")));
+        assert_eq!(resolve(&root, "/chapter?q=1#part"), Some(root.join("chapter.html")));
+        assert_eq!(resolve(&root, "/nested"), Some(root.join("nested/index.html")));
+        assert_eq!(resolve(&root, "/nested/"), Some(root.join("nested/index.html")));
+        assert_eq!(resolve(&root, "/with%20space.html"), Some(root.join("with space.html")));
+        assert_eq!(resolve(&root, "/missing.html"), None);
+    }
+
+    #[test]
+    fn resolve_stays_in_root() {
+        let (_tmp, root) = site();
+
+        assert_eq!(resolve(&root, "/../secret.html"), None);
+        assert_eq!(resolve(&root, "/nested/../../secret.html"), None);
+        assert_eq!(resolve(&root, "/%2e%2e/secret.html"), None);
+        assert_eq!(resolve(&root, "/nested/%2E%2E%2F%2e%2e%2Fsecret.html"), None);
+        assert_eq!(resolve(&root, "/..%5csecret.html"), None);
+    }
+
+    #[test]
+    fn request_parse() {
+        let head = "GET /__livereload HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: WebSocket";
+        assert_eq!(Request::parse(head),
+                   Some(Request {
+                            method: "GET",
+                            resource: "/__livereload",
+                            websocket: true,
+                        }));
+
+        let head = "HEAD /index.html HTTP/1.0\r\nHost: localhost";
+        assert_eq!(Request::parse(head).map(|r| (r.method, r.websocket)), Some(("HEAD", false)));
+
+        assert_eq!(Request::parse("garbage"), None);
+        assert_eq!(Request::parse("GET index.html HTTP/1.1"), None);
+        assert_eq!(Request::parse("GET / SPDY/3"), None);
+    }
+
+    #[test]
+    fn get_sends_the_file() {
+        let (_tmp, root) = site();
+        let response = respond(&root, "GET /chapter HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(response.contains("Content-Length: 17\r\n"));
+        assert!(response.ends_with("\r\n\r\npage chapter.html"));
+    }
+
+    #[test]
+    fn head_sends_no_body() {
+        let (_tmp, root) = site();
+        let response = respond(&root, "HEAD /nested/ HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 22\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn ranges_are_ignored() {
+        let (_tmp, root) = site();
+        let response = respond(&root, "GET /index.html HTTP/1.1\r\nRange: bytes=0-1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Accept-Ranges: none\r\n"));
+        assert!(response.ends_with("page index.html"));
+    }
+
+    #[test]
+    fn missing_pages_get_the_404_page() {
+        let (_tmp, root) = site();
+
+        let response = respond(&root, "GET /%2e%2e/secret.html HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.ends_with("page 404.html"));
+
+        fs::remove_file(root.join("404.html")).unwrap();
+        let response = respond(&root, "GET /missing HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.ends_with("\r\n\r\nNot Found"));
+    }
+
+    #[test]
+    fn other_requests_are_refused() {
+        let (_tmp, root) = site();
+
+        assert!(respond(&root, "POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n").starts_with("HTTP/1.1 501 "));
+        assert!(respond(&root, "hello\r\n\r\n").starts_with("HTTP/1.1 400 "));
+    }
+
+    #[test]
+    fn large_files_are_sent_whole() {
+        let (_tmp, root) = site();
+        let content = vec![b'x'; 4 * 1024 * 1024];
+        fs::File::create(root.join("large.txt")).unwrap().write_all(&content).unwrap();
+
+        let response = respond(&root, "GET /large.txt HTTP/1.1\r\n\r\n");
+        assert!(response.contains("Content-Length: 4194304\r\n"));
+        assert!(response.ends_with(&*String::from_utf8(content).unwrap()));
+    }
+}
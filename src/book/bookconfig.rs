@@ -1,63 +1,154 @@
-extern crate toml;
-
-use std::process::exit;
+use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::fmt;
+use std::error::Error;
 use std::path::{Path, PathBuf};
 use std::collections::BTreeMap;
 use std::str::FromStr;
+use serde::de::DeserializeOwned;
 use serde_json;
-
-#[derive(Debug, Clone)]
+use toml;
+
+/// The configuration of a book, read from `book.toml` (or `book.json`).
+///
+/// ```toml
+/// [book]
+/// title = "Example book"
+/// author = "Name"
+///
+/// [build]
+/// dest = "output/my-book"
+///
+/// [output.html]
+/// ```
+///
+/// The `[book]` and `[build]` tables are deserialized into [`BookMetadata`](struct.BookMetadata.html)
+/// and [`BuildConfig`](struct.BuildConfig.html). The `[output.<name>]` and `[preprocessor.<name>]`
+/// tables, and any other top-level table, are kept as `toml::Value` for renderers and preprocessors
+/// to deserialize into their own types with [`get_deserialized()`](#method.get_deserialized).
+#[derive(Debug, Clone, Serialize)]
 pub struct BookConfig {
     root: PathBuf,
-    pub dest: PathBuf,
-    pub src: PathBuf,
-    pub theme_path: PathBuf,
 
+    /// The `[book]` table
+    pub book: BookMetadata,
+    /// The `[build]` table
+    pub build: BuildConfig,
+
+    /// The `[output.<name>]` tables, one for every renderer to run
+    pub output: BTreeMap<String, toml::Value>,
+    /// The `[preprocessor.<name>]` tables
+    pub preprocessor: BTreeMap<String, toml::Value>,
+    /// Any other top-level table
+    pub extra: BTreeMap<String, toml::Value>,
+
+    /// Values set on top of the configuration file, by dotted key
+    #[serde(skip)]
+    overrides: Vec<(String, toml::Value)>,
+}
+
+/// Metadata and sources of the book, the `[book]` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BookMetadata {
     pub title: String,
     pub author: String,
     pub description: String,
-
-    pub indent_spaces: i32,
-    multilingual: bool,
+    /// The language of the book, as used in the `lang` attribute of the html pages
+    pub language: String,
+    /// The directory containing `SUMMARY.md` and the chapters
+    pub src: PathBuf,
+    pub multilingual: bool,
 }
 
-impl BookConfig {
-    pub fn new(root: &Path) -> Self {
-        BookConfig {
-            root: root.to_owned(),
-            dest: root.join("book"),
-            src: root.join("src"),
-            theme_path: root.join("theme"),
-
+impl Default for BookMetadata {
+    fn default() -> Self {
+        BookMetadata {
             title: String::new(),
             author: String::new(),
             description: String::new(),
-
-            indent_spaces: 4, // indentation used for SUMMARY.md
+            language: String::from("en"),
+            src: PathBuf::from("src"),
             multilingual: false,
         }
     }
+}
 
-    pub fn read_config(&mut self, root: &Path) -> &mut Self {
+const BOOK_KEYS: &'static [&'static str] = &["title", "author", "description", "language", "src", "multilingual"];
+
+/// How the book is built, the `[build]` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BuildConfig {
+    /// The output directory
+    pub dest: PathBuf,
+    /// The directory of a custom theme
+    pub theme_path: PathBuf,
+    /// Create the files referenced from `SUMMARY.md` that don't exist
+    pub create_missing: bool,
+    /// Use `.html` in the urls of the pages
+    pub page_extension: bool,
+    /// Number the chapters listed in `SUMMARY.md`, like `2.3.1.`
+    pub number_sections: bool,
+    /// The preprocessors to run, in order. `None` runs all registered preprocessors.
+    pub preprocess: Option<Vec<String>>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            dest: PathBuf::from("book"),
+            theme_path: PathBuf::from("theme"),
+            create_missing: true,
+            page_extension: false,
+            number_sections: true,
+            preprocess: None,
+        }
+    }
+}
+
+const BUILD_KEYS: &'static [&'static str] = &["dest", "theme-path", "create-missing", "page-extension",
+                                              "number-sections", "preprocess"];
+
+// Keys that used to be at the top level of `book.toml`, and the table they moved to
+const LEGACY_KEYS: &'static [(&'static str, &'static str, &'static str)] = &[("title", "book", "title"),
+                                                                             ("author", "book", "author"),
+                                                                             ("description", "book", "description"),
+                                                                             ("src", "book", "src"),
+                                                                             ("dest", "build", "dest"),
+                                                                             ("theme_path", "build", "theme-path"),
+                                                                             ("preprocess", "build", "preprocess")];
+
+impl BookConfig {
+    pub fn new(root: &Path) -> Self {
+        let mut config = BookConfig {
+            root: root.to_owned(),
+            book: BookMetadata::default(),
+            build: BuildConfig::default(),
+            output: BTreeMap::new(),
+            preprocessor: BTreeMap::new(),
+            extra: BTreeMap::new(),
+            overrides: vec![],
+        };
+        config.book.src = root.join("src");
+        config.build.dest = root.join("book");
+        config.build.theme_path = root.join("theme");
+        config
+    }
+
+    /// Reads `book.toml`, or `book.json` if there is no `book.toml`, from the `root` directory.
+    /// Keeps the defaults if there is neither.
+    pub fn read_config(&mut self, root: &Path) -> Result<&mut Self, ConfigError> {
 
         debug!("[fn]: read_config");
 
-        let read_file = |path: PathBuf| -> String {
+        let read_file = |path: &Path| -> Result<String, ConfigError> {
             let mut data = String::new();
-            let mut f: File = match File::open(&path) {
-                Ok(x) => x,
-                Err(_) => {
-                    error!("[*]: Failed to open {:?}", &path);
-                    exit(2);
-                }
-            };
-            if f.read_to_string(&mut data).is_err() {
-                error!("[*]: Failed to read {:?}", &path);
-                exit(2);
-            }
-            data
+            try!(File::open(path)
+                     .and_then(|mut f| f.read_to_string(&mut data))
+                     .map_err(|e| ConfigError::new(path, format!("Failed to read the configuration: {}", e))));
+            Ok(data)
         };
 
         // Read book.toml or book.json if exists
@@ -65,98 +156,223 @@ impl BookConfig {
         if root.join("book.toml").exists() {
 
             debug!("[*]: Reading config");
-            let data = read_file(root.join("book.toml"));
-            self.parse_from_toml_string(&data);
+            let data = try!(read_file(&root.join("book.toml")));
+            try!(self.parse_from_toml_string(&data));
 
         } else if root.join("book.json").exists() {
 
             debug!("[*]: Reading config");
-            let data = read_file(root.join("book.json"));
-            self.parse_from_json_string(&data);
+            let data = try!(read_file(&root.join("book.json")));
+            try!(self.parse_from_json_string(&data));
 
         } else {
             debug!("[*]: No book.toml or book.json was found, using defaults.");
+            try!(self.parse_from_btreemap(&BTreeMap::new()));
+        }
+
+        Ok(self)
+    }
+
+    /// Sets `key`, a dotted path like `"build.dest"`, to `value` on top of the configuration
+    /// file. Overrides are applied in the order they were added, the next time the configuration is
+    /// read or parsed.
+    pub fn set_override(&mut self, key: &str, value: toml::Value) -> &mut Self {
+        self.overrides.push((key.to_owned(), value));
+        self
+    }
+
+    pub fn get_overrides(&self) -> &[(String, toml::Value)] {
+        &self.overrides
+    }
+
+    /// Adds an override for every `MDBOOK_<TABLE>__<KEY>` environment variable. Double underscores
+    /// separate the levels of the key and single underscores stand for dashes, so
+    /// `MDBOOK_BUILD__CREATE_MISSING=false` sets `build.create-missing`. The values are parsed with
+    /// [`parse_override_value()`](fn.parse_override_value.html).
+    pub fn set_env_overrides(&mut self) -> &mut Self {
+        let mut vars: Vec<(String, String)> = env::vars_os()
+                                                  .filter_map(|(name, value)| {
+                                                      match (name.into_string(), value.into_string()) {
+                                                          (Ok(name), Ok(value)) => Some((name, value)),
+                                                          _ => None,
+                                                      }
+                                                  })
+                                                  .collect();
+        vars.sort();
+
+        for (name, value) in vars {
+            if let Some(key) = env_var_to_key(&name) {
+                debug!("[*]: Overriding `{}` with ${}", key, name);
+                self.set_override(&key, parse_override_value(&value));
+            }
         }
 
         self
     }
 
-    pub fn parse_from_toml_string(&mut self, data: &str) -> &mut Self {
+    /// Parses the content of a `book.toml` file. Errors point to `book.toml` in the root directory.
+    pub fn parse_from_toml_string(&mut self, data: &str) -> Result<&mut Self, ConfigError> {
+        let path = self.root.join("book.toml");
+
         let config = match toml::from_str(data) {
-            Ok(x) => {x},
+            Ok(x) => x,
             Err(e) => {
-                error!("[*]: Toml parse errors in book.toml: {:?}", e);
-                exit(2);
-            }
+                let error = ConfigError::new(&path, e.to_string());
+                return Err(match e.line_col() {
+                    Some((line, column)) => error.at(data, line + 1, column + 1),
+                    None => error,
+                });
+            },
         };
 
-        self.parse_from_btreemap(&config);
+        self.apply(config).map_err(|invalid| {
+            if invalid.overridden {
+                return ConfigError::new(&path, format!("{} (set by an override)", invalid.message));
+            }
 
-        self
+            let error = ConfigError::new(&path, invalid.message);
+            match locate_toml_key(data, &invalid.table, &invalid.key) {
+                Some((line, column)) => error.at(data, line, column),
+                None => error,
+            }
+        })
     }
 
-    /// Parses the string to JSON and converts it to BTreeMap<String, toml::Value>.
-    pub fn parse_from_json_string(&mut self, data: &str) -> &mut Self {
+    /// Parses the string to JSON and converts it to BTreeMap<String, toml::Value>. Errors point to
+    /// `book.json` in the root directory.
+    pub fn parse_from_json_string(&mut self, data: &str) -> Result<&mut Self, ConfigError> {
+        let path = self.root.join("book.json");
 
-        println!("bookconfig parsing");
         let c: serde_json::Value = match serde_json::from_str(data) {
             Ok(x) => x,
-            Err(e) => {
-                error!("[*]: JSON parse errors in book.json: {:?}", e);
-                exit(2);
-            }
+            Err(e) => return Err(ConfigError::new(&path, e.to_string()).at(data, e.line(), e.column())),
         };
 
-        let config = json_object_to_btreemap(c.as_object().unwrap());
-        self.parse_from_btreemap(&config);
-        println!("bookconfig parsed");
+        let config = match c.as_object() {
+            Some(object) => json_object_to_btreemap(object),
+            None => return Err(ConfigError::new(&path, "The configuration should be a JSON object".to_owned())),
+        };
 
-        self
-    }
+        self.apply(config).map_err(|invalid| {
+            if invalid.overridden {
+                return ConfigError::new(&path, format!("{} (set by an override)", invalid.message));
+            }
 
-    pub fn parse_from_btreemap(&mut self, config: &BTreeMap<String, toml::Value>) -> &mut Self {
+            let error = ConfigError::new(&path, invalid.message);
+            match locate_json_key(data, &invalid.table, &invalid.key) {
+                Some((line, column)) => error.at(data, line, column),
+                None => error,
+            }
+        })
+    }
 
-        // Title, author, description
-        if let Some(a) = config.get("title") {
-            self.title = a.to_string().replace("\"", "");
-        }
-        if let Some(a) = config.get("author") {
-            self.author = a.to_string().replace("\"", "");
-        }
-        if let Some(a) = config.get("description") {
-            self.description = a.to_string().replace("\"", "");
-        }
+    /// Applies an already parsed configuration. Errors point to `book.toml` in the root directory,
+    /// without position.
+    pub fn parse_from_btreemap(&mut self, config: &BTreeMap<String, toml::Value>) -> Result<&mut Self, ConfigError> {
+        let path = self.root.join("book.toml");
+        self.apply(config.clone()).map_err(|invalid| ConfigError::new(&path, invalid.message))
+    }
 
-        // Destination folder
-        if let Some(a) = config.get("dest") {
-            let mut dest = PathBuf::from(&a.to_string().replace("\"", ""));
+    fn apply(&mut self, mut config: BTreeMap<String, toml::Value>) -> Result<&mut Self, InvalidKey> {
+        move_legacy_keys(&mut config);
 
-            // If path is relative make it absolute from the parent directory of src
-            if dest.is_relative() {
-                dest = self.get_root().join(&dest);
-            }
-            self.set_dest(&dest);
+        for &(ref key, ref value) in &self.overrides {
+            insert_dotted(&mut config, key, value.clone());
         }
 
-        // Source folder
-        if let Some(a) = config.get("src") {
-            let mut src = PathBuf::from(&a.to_string().replace("\"", ""));
-            if src.is_relative() {
-                src = self.get_root().join(&src);
+        for (key, value) in config {
+            match key.as_str() {
+                "book" => {
+                    self.book = try!(deserialize_table("book", value, BOOK_KEYS)
+                                         .map_err(|e| e.mark_overridden(&self.overrides)));
+                    // Relative paths are relative to the root of the book
+                    self.book.src = self.root.join(&self.book.src);
+                },
+                "build" => {
+                    self.build = try!(deserialize_table("build", value, BUILD_KEYS)
+                                          .map_err(|e| e.mark_overridden(&self.overrides)));
+                    self.build.dest = self.root.join(&self.build.dest);
+                    self.build.theme_path = self.root.join(&self.build.theme_path);
+                },
+                "output" | "preprocessor" => {
+                    let table = match value {
+                        toml::Value::Table(table) => table,
+                        _ => {
+                            let invalid = InvalidKey {
+                                table: String::new(),
+                                message: format!("`{}` should be a table", key),
+                                key: key,
+                                overridden: false,
+                            };
+                            return Err(invalid.mark_overridden(&self.overrides));
+                        },
+                    };
+
+                    if key == "output" {
+                        self.output = table;
+                    } else {
+                        self.preprocessor = table;
+                    }
+                },
+                _ => {
+                    match value {
+                        toml::Value::Table(_) => {
+                            self.extra.insert(key, value);
+                        },
+                        _ => warn!("[*]: Unknown key `{}` in the configuration, ignoring it", key),
+                    }
+                },
             }
-            self.set_src(&src);
         }
 
-        // Theme path folder
-        if let Some(a) = config.get("theme_path") {
-            let mut theme_path = PathBuf::from(&a.to_string().replace("\"", ""));
-            if theme_path.is_relative() {
-                theme_path = self.get_root().join(&theme_path);
-            }
-            self.set_theme_path(&theme_path);
+        Ok(self)
+    }
+
+    /// Returns the value at `key`, a dotted path into the configuration like
+    /// `"output.html.search"`.
+    pub fn get(&self, key: &str) -> Option<toml::Value> {
+        let mut parts = key.split('.');
+
+        let mut value = match parts.next() {
+            Some("book") => toml::Value::try_from(&self.book).ok()?,
+            Some("build") => toml::Value::try_from(&self.build).ok()?,
+            Some("output") => toml::Value::Table(self.output.clone()),
+            Some("preprocessor") => toml::Value::Table(self.preprocessor.clone()),
+            Some(other) => self.extra.get(other)?.clone(),
+            None => return None,
+        };
+
+        for part in parts {
+            let next = value.as_table()?.get(part)?.clone();
+            value = next;
         }
 
-        self
+        Some(value)
+    }
+
+    /// Deserializes the value at `key` (see [`get()`](#method.get)) into `T`. Returns `None` if
+    /// the key is absent.
+    ///
+    /// ```
+    /// # extern crate mdbook;
+    /// # #[macro_use] extern crate serde_derive;
+    /// # use mdbook::BookConfig;
+    /// # use std::path::Path;
+    /// #[derive(Deserialize)]
+    /// struct EmojiConfig {
+    ///     skin_tone: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut config = BookConfig::new(Path::new("."));
+    /// config.parse_from_toml_string("[preprocessor.emoji]\nskin_tone = \"medium\"").unwrap();
+    ///
+    /// let emoji: EmojiConfig = config.get_deserialized("preprocessor.emoji").unwrap().unwrap();
+    /// assert_eq!(emoji.skin_tone, "medium");
+    /// # }
+    /// ```
+    pub fn get_deserialized<T: DeserializeOwned>(&self, key: &str) -> Option<Result<T, toml::de::Error>> {
+        self.get(key).map(|value| value.try_into())
     }
 
     pub fn get_root(&self) -> &Path {
@@ -169,33 +385,261 @@ impl BookConfig {
     }
 
     pub fn get_dest(&self) -> &Path {
-        &self.dest
+        &self.build.dest
     }
 
     pub fn set_dest(&mut self, dest: &Path) -> &mut Self {
-        self.dest = dest.to_owned();
+        self.build.dest = dest.to_owned();
         self
     }
 
     pub fn get_src(&self) -> &Path {
-        &self.src
+        &self.book.src
     }
 
     pub fn set_src(&mut self, src: &Path) -> &mut Self {
-        self.src = src.to_owned();
+        self.book.src = src.to_owned();
         self
     }
 
     pub fn get_theme_path(&self) -> &Path {
-        &self.theme_path
+        &self.build.theme_path
     }
 
     pub fn set_theme_path(&mut self, theme_path: &Path) -> &mut Self {
-        self.theme_path = theme_path.to_owned();
+        self.build.theme_path = theme_path.to_owned();
         self
     }
 }
 
+// Moves the keys of the old flat format into their table, with a warning
+fn move_legacy_keys(config: &mut BTreeMap<String, toml::Value>) {
+    for &(key, table, new_key) in LEGACY_KEYS {
+        let value = match config.remove(key) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        warn!("[*]: `{}` is deprecated, use `{}` in the [{}] table instead", key, new_key, table);
+
+        let entry = config.entry(table.to_owned()).or_insert_with(|| toml::Value::Table(BTreeMap::new()));
+        if let toml::Value::Table(ref mut table) = *entry {
+            // The new format wins when both are given
+            table.entry(new_key.to_owned()).or_insert(value);
+        }
+    }
+}
+
+// Sets the value at a dotted `key`, creating the missing tables on the way
+fn insert_dotted(config: &mut BTreeMap<String, toml::Value>, key: &str, value: toml::Value) {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().expect("split always yields a part");
+
+    let mut table = config;
+    for part in parts {
+        let entry = table.entry(part.to_owned()).or_insert_with(|| toml::Value::Table(BTreeMap::new()));
+        if !entry.is_table() {
+            *entry = toml::Value::Table(BTreeMap::new());
+        }
+        table = match *entry {
+            toml::Value::Table(ref mut table) => table,
+            _ => unreachable!(),
+        };
+    }
+
+    table.insert(last.to_owned(), value);
+}
+
+// `MDBOOK_BUILD__CREATE_MISSING` -> `build.create-missing`
+fn env_var_to_key(name: &str) -> Option<String> {
+    if !name.starts_with("MDBOOK_") || !name.contains("__") {
+        return None;
+    }
+
+    let parts: Vec<String> = name["MDBOOK_".len()..].split("__")
+                                                    .map(|part| part.to_lowercase().replace('_', "-"))
+                                                    .collect();
+    Some(parts.join("."))
+}
+
+/// Parses the value of an override. Valid TOML values like `true`, `42` or `["a", "b"]` keep
+/// their type, anything else is taken as a string.
+pub fn parse_override_value(value: &str) -> toml::Value {
+    let parsed: Result<BTreeMap<String, toml::Value>, _> = toml::from_str(&format!("value = {}", value));
+    match parsed.ok().and_then(|mut table| table.remove("value")) {
+        Some(value) => value,
+        None => toml::Value::String(value.to_owned()),
+    }
+}
+
+/// Parses a `key=value` override, as given on the command line
+pub fn parse_override(arg: &str) -> Result<(String, toml::Value), String> {
+    let mut parts = arg.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_owned(), parse_override_value(value.trim())))
+        },
+        _ => Err(format!("Invalid override `{}`, expected `key=value`", arg)),
+    }
+}
+
+// A key with an invalid value, found after parsing
+struct InvalidKey {
+    table: String,
+    key: String,
+    message: String,
+    // Set by an override rather than by the file
+    overridden: bool,
+}
+
+impl InvalidKey {
+    fn mark_overridden(mut self, overrides: &[(String, toml::Value)]) -> Self {
+        let key = if self.table.is_empty() {
+            self.key.clone()
+        } else {
+            format!("{}.{}", self.table, self.key)
+        };
+
+        // Overriding a whole table counts too
+        self.overridden = overrides.iter()
+                                   .any(|&(ref k, _)| *k == key || (!self.table.is_empty() && *k == self.table));
+        self
+    }
+}
+
+// Deserializes the `name` table, warning about the keys it doesn't know
+fn deserialize_table<T: DeserializeOwned>(name: &str, value: toml::Value, known: &[&str]) -> Result<T, InvalidKey> {
+    let table = match value {
+        toml::Value::Table(table) => table,
+        _ => {
+            return Err(InvalidKey {
+                table: String::new(),
+                key: name.to_owned(),
+                message: format!("`{}` should be a table", name),
+                overridden: false,
+            })
+        },
+    };
+
+    for key in table.keys().filter(|key| !known.contains(&key.as_str())) {
+        warn!("[*]: Unknown key `{}.{}` in the configuration, ignoring it", name, key);
+    }
+
+    // All the fields have a default, so deserializing the keys one by one tells which one is invalid
+    for (key, value) in &table {
+        let mut single = BTreeMap::new();
+        single.insert(key.clone(), value.clone());
+
+        let result: Result<T, _> = toml::Value::Table(single).try_into();
+        if let Err(e) = result {
+            return Err(InvalidKey {
+                table: name.to_owned(),
+                key: key.clone(),
+                message: format!("Invalid value for `{}.{}`: {}", name, key, e),
+                overridden: false,
+            });
+        }
+    }
+
+    toml::Value::Table(table).try_into().map_err(|e| {
+        InvalidKey {
+            table: name.to_owned(),
+            key: String::new(),
+            message: format!("Invalid [{}] table: {}", name, e),
+            overridden: false,
+        }
+    })
+}
+
+// The 1-based line and column of the value of `key` in the `[table]` of a TOML file. Keys moved
+// from the top level are looked up there too.
+fn locate_toml_key(data: &str, table: &str, key: &str) -> Option<(usize, usize)> {
+    let find_in = |table: &str| {
+        let mut current = "";
+        for (i, line) in data.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                current = trimmed.trim_matches(|c| c == '[' || c == ']').trim();
+                continue;
+            }
+
+            if current != table || !trimmed.starts_with(key) {
+                continue;
+            }
+            let rest = trimmed[key.len()..].trim_left();
+            if rest.starts_with('=') {
+                let value = rest[1..].trim_left();
+                return Some((i + 1, line.len() - value.len() + 1));
+            }
+        }
+        None
+    };
+
+    find_in(table).or_else(|| {
+        LEGACY_KEYS.iter()
+                   .find(|&&(_, t, k)| t == table && k == key)
+                   .and_then(|&(legacy, _, _)| locate_toml_key(data, "", legacy))
+    })
+}
+
+// The 1-based line and column of `"key"` in the `"table"` object of a JSON file
+fn locate_json_key(data: &str, table: &str, key: &str) -> Option<(usize, usize)> {
+    let start = if table.is_empty() {
+        0
+    } else {
+        data.find(&format!("\"{}\"", table)).unwrap_or(0)
+    };
+    let offset = start + data[start..].find(&format!("\"{}\"", key))?;
+
+    let line = data[..offset].matches('\n').count() + 1;
+    let column = offset - data[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    Some((line, column))
+}
+
+/// An error in the configuration file, with the position of the error when it is known
+#[derive(Debug)]
+pub struct ConfigError {
+    /// The configuration file
+    pub path: PathBuf,
+    /// The 1-based line and column of the error
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+    /// The line of the configuration file containing the error
+    pub source_line: Option<String>,
+}
+
+impl ConfigError {
+    fn new(path: &Path, message: String) -> Self {
+        ConfigError {
+            path: path.to_owned(),
+            position: None,
+            message: message,
+            source_line: None,
+        }
+    }
+
+    fn at(mut self, data: &str, line: usize, column: usize) -> Self {
+        self.position = Some((line, column));
+        self.source_line = data.lines().nth(line.saturating_sub(1)).map(|l| l.to_owned());
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}", self.path.display(), line, column, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
 pub fn json_object_to_btreemap(json: &serde_json::Map<String, serde_json::Value>) -> BTreeMap<String, toml::Value> {
     let mut config: BTreeMap<String, toml::Value> = BTreeMap::new();
 
@@ -214,7 +658,7 @@ pub fn json_value_to_toml_value(json: serde_json::Value) -> toml::Value {
         serde_json::Value::Null => toml::Value::String("".to_string()),
         serde_json::Value::Bool(x) => toml::Value::Boolean(x),
         serde_json::Value::Number(ref x) if x.is_i64() => toml::Value::Integer(x.as_i64().unwrap()),
-        serde_json::Value::Number(ref x) if x.is_u64() => toml::Value::Integer(x.as_i64().unwrap()),
+        // TOML integers are i64, larger numbers can only be kept as floats
         serde_json::Value::Number(x) => toml::Value::Float(x.as_f64().unwrap()),
         serde_json::Value::String(x) => toml::Value::String(x),
         serde_json::Value::Array(x) => {
@@ -1,27 +1,31 @@
 #![cfg(test)]
 
+use std::env;
 use std::path::Path;
 use serde_json;
+use toml;
 use book::bookconfig::*;
 
 #[test]
 fn it_parses_json_config() {
     let text = r#"
 {
-    "title": "mdBook Documentation",
-    "description": "Create book from markdown files. Like Gitbook but implemented in Rust",
-    "author": "Mathieu David"
+    "book": {
+        "title": "mdBook Documentation",
+        "description": "Create book from markdown files. Like Gitbook but implemented in Rust",
+        "author": "Mathieu David"
+    }
 }"#;
 
     // TODO don't require path argument, take pwd
     let mut config = BookConfig::new(Path::new("."));
 
-    config.parse_from_json_string(&text.to_string());
+    config.parse_from_json_string(&text.to_string()).unwrap();
 
     let mut expected = BookConfig::new(Path::new("."));
-    expected.title = "mdBook Documentation".to_string();
-    expected.author = "Mathieu David".to_string();
-    expected.description = "Create book from markdown files. Like Gitbook but implemented in Rust".to_string();
+    expected.book.title = "mdBook Documentation".to_string();
+    expected.book.author = "Mathieu David".to_string();
+    expected.book.description = "Create book from markdown files. Like Gitbook but implemented in Rust".to_string();
 
     assert_eq!(format!("{:#?}", config), format!("{:#?}", expected));
 }
@@ -29,24 +33,179 @@ fn it_parses_json_config() {
 #[test]
 fn it_parses_toml_config() {
     let text = r#"
+[book]
 title = "mdBook Documentation"
 description = "Create book from markdown files. Like Gitbook but implemented in Rust"
 author = "Mathieu David"
+language = "fr"
+multilingual = true
+
+[build]
+dest = "output"
+create-missing = false
+preprocess = ["playpen"]
 "#;
 
     // TODO don't require path argument, take pwd
     let mut config = BookConfig::new(Path::new("."));
 
-    config.parse_from_toml_string(&text.to_string());
+    config.parse_from_toml_string(&text.to_string()).unwrap();
 
     let mut expected = BookConfig::new(Path::new("."));
-    expected.title = "mdBook Documentation".to_string();
-    expected.author = "Mathieu David".to_string();
-    expected.description = "Create book from markdown files. Like Gitbook but implemented in Rust".to_string();
+    expected.book.title = "mdBook Documentation".to_string();
+    expected.book.author = "Mathieu David".to_string();
+    expected.book.description = "Create book from markdown files. Like Gitbook but implemented in Rust".to_string();
+    expected.book.language = "fr".to_string();
+    expected.book.multilingual = true;
+    expected.build.dest = Path::new(".").join("output");
+    expected.build.create_missing = false;
+    expected.build.preprocess = Some(vec!["playpen".to_string()]);
 
     assert_eq!(format!("{:#?}", config), format!("{:#?}", expected));
 }
 
+#[test]
+fn it_moves_legacy_keys_to_their_table() {
+    let text = r#"
+title = "mdBook Documentation"
+author = "Mathieu David"
+dest = "output"
+"#;
+
+    let mut config = BookConfig::new(Path::new("."));
+
+    config.parse_from_toml_string(&text.to_string()).unwrap();
+
+    let mut expected = BookConfig::new(Path::new("."));
+    expected.book.title = "mdBook Documentation".to_string();
+    expected.book.author = "Mathieu David".to_string();
+    expected.build.dest = Path::new(".").join("output");
+
+    assert_eq!(format!("{:#?}", config), format!("{:#?}", expected));
+}
+
+#[test]
+fn it_exposes_extra_tables() {
+    let text = r#"
+[output.html.search]
+limit-results = 10
+
+[preprocessor.emoji]
+command = "mdbook-emoji"
+
+[custom]
+answer = 42
+"#;
+
+    let mut config = BookConfig::new(Path::new("."));
+
+    config.parse_from_toml_string(&text.to_string()).unwrap();
+
+    assert_eq!(config.get("output.html.search.limit-results").and_then(|v| v.as_integer()), Some(10));
+    assert_eq!(config.get("preprocessor.emoji.command").and_then(|v| v.as_str().map(|s| s.to_owned())),
+               Some("mdbook-emoji".to_owned()));
+    assert_eq!(config.get("book.language").and_then(|v| v.as_str().map(|s| s.to_owned())),
+               Some("en".to_owned()));
+    assert!(config.get("output.epub").is_none());
+
+    let answer: i64 = config.get_deserialized("custom.answer").unwrap().unwrap();
+    assert_eq!(answer, 42);
+}
+
+#[test]
+fn it_reports_the_position_of_syntax_errors() {
+    let text = "[book]\ntitle = \"mdBook Documentation\"\nauthor\n";
+
+    let mut config = BookConfig::new(Path::new("."));
+    let error = config.parse_from_toml_string(text).unwrap_err();
+
+    assert_eq!(error.path, Path::new(".").join("book.toml"));
+    assert_eq!(error.position.map(|(line, _)| line), Some(3));
+    assert_eq!(error.source_line, Some("author".to_string()));
+}
+
+#[test]
+fn it_reports_the_position_of_invalid_values() {
+    let text = r#"
+[book]
+title = "mdBook Documentation"
+
+[build]
+create-missing = "no"
+"#;
+
+    let mut config = BookConfig::new(Path::new("."));
+    let error = config.parse_from_toml_string(text).unwrap_err();
+
+    assert_eq!(error.position, Some((6, 18)));
+    assert!(error.message.contains("build.create-missing"));
+
+    let text = r#"{
+    "book": {
+        "title": 42
+    }
+}"#;
+
+    let error = config.parse_from_json_string(text).unwrap_err();
+
+    assert_eq!(error.path, Path::new(".").join("book.json"));
+    assert_eq!(error.position, Some((3, 9)));
+}
+
+#[test]
+fn it_applies_overrides_on_top_of_the_file() {
+    let text = r#"
+[book]
+title = "mdBook Documentation"
+
+[build]
+dest = "output"
+"#;
+
+    let mut config = BookConfig::new(Path::new("."));
+    config.set_override("book.title", parse_override_value("Draft"));
+    config.set_override("build.create-missing", parse_override_value("false"));
+    config.set_override("output.html.search.limit-results", parse_override_value("10"));
+
+    config.parse_from_toml_string(&text.to_string()).unwrap();
+
+    assert_eq!(config.book.title, "Draft");
+    assert_eq!(config.build.dest, Path::new(".").join("output"));
+    assert_eq!(config.build.create_missing, false);
+    assert_eq!(config.get("output.html.search.limit-results").and_then(|v| v.as_integer()), Some(10));
+
+    let mut config = BookConfig::new(Path::new("."));
+    config.set_override("build.create-missing", parse_override_value("maybe"));
+
+    let error = config.parse_from_toml_string("[build]\ncreate-missing = true\n").unwrap_err();
+    assert_eq!(error.position, None);
+}
+
+#[test]
+fn it_reads_overrides_from_the_environment() {
+    env::set_var("MDBOOK_OUTPUT__ENV_TEST__SOME_KEY", "[1, 2]");
+
+    let mut config = BookConfig::new(Path::new("."));
+    config.set_env_overrides();
+
+    env::remove_var("MDBOOK_OUTPUT__ENV_TEST__SOME_KEY");
+
+    config.parse_from_toml_string("").unwrap();
+    assert_eq!(config.get("output.env-test.some-key").and_then(|v| v.as_array().map(|a| a.len())), Some(2));
+}
+
+#[test]
+fn it_parses_command_line_overrides() {
+    let (key, value) = parse_override("build.dest=out/book").unwrap();
+    assert_eq!(key, "build.dest");
+    assert_eq!(value.as_str(), Some("out/book"));
+
+    let (_, value) = parse_override("book.multilingual = true").unwrap();
+    assert_eq!(value.as_bool(), Some(true));
+
+    assert!(parse_override("book.title").is_err());
+}
+
 #[test]
 fn it_parses_json_nested_array_to_toml() {
 
@@ -347,3 +506,17 @@ fn it_parses_json_arrays_to_toml() {
 
     assert_eq!(format!("{:#?}", result), expected);
 }
+
+
+#[test]
+fn it_keeps_numbers_too_large_for_toml_as_floats() {
+    let json: serde_json::Value = serde_json::from_str("[42, 18446744073709551615]").unwrap();
+
+    match json_value_to_toml_value(json) {
+        toml::Value::Array(values) => {
+            assert_eq!(values[0], toml::Value::Integer(42));
+            assert_eq!(values[1], toml::Value::Float(18446744073709551615u64 as f64));
+        },
+        other => panic!("Expected an array, got {:?}", other),
+    }
+}
@@ -1,6 +1,9 @@
-
 use serde::ser::{Serialize, Serializer, SerializeStruct};
-use std::path::PathBuf;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use errors;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -11,26 +14,68 @@ pub enum BookItem {
 }
 
 impl BookItem {
-    pub fn prepend(&mut self, pre: &Chapter) {
-        use self::BookItem::*;
+    /// Prefixes the paths of this item and its sub-items with `dir`, to make the items of an
+    /// included `SUMMARY.md` relative to the including one
+    pub fn prepend(&mut self, dir: &Path) {
         match *self {
-            Chapter(ref mut ch) => {
-                ch.path = pre.path.join(&ch.path);
+            BookItem::Chapter(ref mut ch) |
+            BookItem::Affix(ref mut ch) => {
+                if !ch.is_draft() {
+                    ch.path = dir.join(&ch.path);
+                }
                 for item in &mut ch.sub_items {
-                    item.prepend(pre);
+                    item.prepend(dir);
                 }
             },
-            Affix(..) => (),
-            _ => (),
+            BookItem::Spacer => (),
+        }
+    }
+
+    /// Returns a copy of this item and its sub-items without the content of the chapters, to
+    /// describe the structure of the book
+    pub fn without_content(&self) -> BookItem {
+        match *self {
+            BookItem::Chapter(ref ch) => BookItem::Chapter(ch.without_content()),
+            BookItem::Affix(ref ch) => BookItem::Affix(ch.without_content()),
+            BookItem::Spacer => BookItem::Spacer,
         }
     }
 }
 
+/// The position of a numbered chapter in the book, `[2, 3, 1]` for the first sub-chapter of the
+/// third sub-chapter of the second chapter. Displayed as `2.3.1.`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionNumber(pub Vec<u32>);
+
+impl fmt::Display for SectionNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for n in &self.0 {
+            write!(f, "{}.", n)?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for SectionNumber {
+    type Target = Vec<u32>;
+
+    fn deref(&self) -> &Vec<u32> {
+        &self.0
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Chapter {
     pub name: String,
     pub path: PathBuf,
+    /// The section number of the chapter, `None` for prefix and suffix chapters or when the
+    /// numbering is disabled
+    #[serde(default)]
+    pub number: Option<SectionNumber>,
+    /// The markdown source of the chapter. Empty until the book has been loaded.
     #[serde(default)]
+    pub content: String,
+    #[serde(default, rename = "subItems")]
     pub sub_items: Vec<BookItem>,
 }
 
@@ -46,9 +91,65 @@ impl Chapter {
         Chapter {
             name: name,
             path: path,
+            number: None,
+            content: String::new(),
             sub_items: vec![],
         }
     }
+
+    /// Whether the chapter is a draft, listed in `SUMMARY.md` without a file: `- [Future Work]()`.
+    /// Drafts appear in the table of contents but have no page.
+    pub fn is_draft(&self) -> bool {
+        self.path.as_os_str().is_empty()
+    }
+
+    /// The name of the chapter, prefixed by its section number if it has one
+    pub fn numbered_name(&self) -> String {
+        match self.number {
+            Some(ref number) => format!("{} {}", number, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    fn without_content(&self) -> Chapter {
+        let mut copy = Chapter::new(self.name.clone(), self.path.clone());
+        copy.number = self.number.clone();
+        copy.sub_items = self.sub_items.iter().map(|item| item.without_content()).collect();
+        copy
+    }
+
+    /// Returns the markdown file backing this chapter, given the book's source directory.
+    ///
+    /// `path` may point to a file with or without its `.md` extension, or to a directory in which
+    /// case its `index.md` is used.
+    pub fn source_path(&self, src: &Path) -> PathBuf {
+        let path = src.join(&self.path);
+        let md = path.with_extension("md");
+
+        if !md.is_file() && path.is_dir() {
+            path.join("index.md")
+        } else {
+            md
+        }
+    }
+}
+
+/// Calls `f` on every chapter (including affixes) in `items`, depth-first, stopping at the first
+/// error.
+pub fn for_each_chapter_mut<F>(items: &mut [BookItem], f: &mut F) -> errors::Result<()>
+    where F: FnMut(&mut Chapter) -> errors::Result<()>
+{
+    for item in items {
+        match *item {
+            BookItem::Chapter(ref mut ch) |
+            BookItem::Affix(ref mut ch) => {
+                f(ch)?;
+                for_each_chapter_mut(&mut ch.sub_items, f)?;
+            },
+            BookItem::Spacer => {},
+        }
+    }
+    Ok(())
 }
 
 
@@ -60,6 +161,8 @@ impl Serialize for Chapter {
         struct_.serialize_field("name", &self.name)?;
         struct_.serialize_field("link", &self.path.with_extension(""))?;
         struct_.serialize_field("path", &self.path)?;
+        struct_.serialize_field("number", &self.number)?;
+        struct_.serialize_field("content", &self.content)?;
         struct_.serialize_field("subItems", &self.sub_items)?;
         struct_.end()
     }
@@ -0,0 +1,190 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use errors::{Error, Result};
+
+/// What the previous builds of a book wrote to the output directories, so that `watch` and `serve`
+/// only rebuild what changed.
+///
+/// It remembers the hash of every file written with `MDBook::write_file()`, which is skipped when
+/// the content is the same, and the hash of the inputs of every page checked with
+/// `MDBook::is_up_to_date()`, which lets renderers skip rendering pages whose inputs didn't change.
+/// After a successful build, the files of the previous builds that weren't produced again, like
+/// the page of a removed chapter, are deleted.
+#[derive(Debug, Default)]
+pub struct BuildCache {
+    // Hash of the content of every file written, by path
+    files: HashMap<PathBuf, u64>,
+    // Hash of the inputs of every page, by path
+    pages: HashMap<PathBuf, u64>,
+    // Files written or found up to date by the current build
+    built: HashSet<PathBuf>,
+    // Files written or removed by the current build
+    changed: Vec<PathBuf>,
+}
+
+impl BuildCache {
+    pub fn new() -> Self {
+        BuildCache::default()
+    }
+
+    /// Whether nothing was built yet
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.pages.is_empty()
+    }
+
+    /// Starts a new build
+    pub fn start(&mut self) {
+        self.built.clear();
+        self.changed.clear();
+    }
+
+    /// The files written or removed by the current build, in that order
+    pub fn changed(&self) -> &[PathBuf] {
+        &self.changed
+    }
+
+    /// Whether the page at `path`, built from `inputs`, is up to date. The page is counted as
+    /// built either way.
+    pub fn is_up_to_date<H: Hash + ?Sized>(&mut self, path: &Path, inputs: &H) -> bool {
+        let inputs = hash(inputs);
+        let up_to_date = self.pages.get(path) == Some(&inputs) && path.exists();
+
+        self.pages.insert(path.to_owned(), inputs);
+        self.built.insert(path.to_owned());
+        up_to_date
+    }
+
+    /// Whether `content` has to be written to `path`, that is when the file doesn't exist or
+    /// holds something else
+    pub fn needs_write(&mut self, path: &Path, content: &[u8]) -> bool {
+        let content = hash(content);
+        let changed = self.files.get(path) != Some(&content) || !path.exists();
+
+        self.files.insert(path.to_owned(), content);
+        self.built.insert(path.to_owned());
+        if changed {
+            self.changed.push(path.to_owned());
+        }
+        changed
+    }
+
+    /// Ends a successful build, removing the files of the previous builds that it didn't produce
+    pub fn finish(&mut self) -> Result<()> {
+        // A page is usually in both maps, it is removed once
+        let stale: BTreeSet<PathBuf> = self.files
+                                           .keys()
+                                           .chain(self.pages.keys())
+                                           .filter(|path| !self.built.contains(*path))
+                                           .cloned()
+                                           .collect();
+
+        for path in stale {
+            debug!("[*]: Removing {:?}, it isn't part of the book anymore", path);
+            self.files.remove(&path);
+            self.pages.remove(&path);
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+            }
+            self.changed.push(path);
+        }
+        Ok(())
+    }
+
+    /// Forgets everything, after a failed build left the output in an unknown state
+    pub fn clear(&mut self) {
+        *self = BuildCache::default();
+    }
+}
+
+fn hash<H: Hash + ?Sized>(value: &H) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::BuildCache;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn it_skips_unchanged_files() {
+        let tmp = TempDir::new("mdbook").unwrap();
+        let path = tmp.path().join("index.html");
+        let mut cache = BuildCache::new();
+
+        cache.start();
+        assert!(cache.needs_write(&path, b"<h1>Hello</h1>"));
+        File::create(&path).unwrap().write_all(b"<h1>Hello</h1>").unwrap();
+
+        cache.start();
+        assert!(!cache.needs_write(&path, b"<h1>Hello</h1>"));
+        assert!(cache.needs_write(&path, b"<h1>Hello World</h1>"));
+    }
+
+    #[test]
+    fn it_checks_the_inputs_of_pages() {
+        let tmp = TempDir::new("mdbook").unwrap();
+        let path = tmp.path().join("intro.html");
+        let mut cache = BuildCache::new();
+
+        assert!(!cache.is_up_to_date(&path, &("# Intro", "chapter.hbs")));
+        File::create(&path).unwrap();
+
+        assert!(cache.is_up_to_date(&path, &("# Intro", "chapter.hbs")));
+        assert!(!cache.is_up_to_date(&path, &("# Introduction", "chapter.hbs")));
+    }
+
+    #[test]
+    fn it_removes_the_files_that_were_not_built_again() {
+        let tmp = TempDir::new("mdbook").unwrap();
+        let kept = tmp.path().join("kept.html");
+        let removed = tmp.path().join("removed.html");
+        let foreign = tmp.path().join("foreign.html");
+        let mut cache = BuildCache::new();
+
+        cache.start();
+        for path in &[&kept, &removed, &foreign] {
+            File::create(path).unwrap();
+        }
+        cache.needs_write(&kept, b"kept");
+        cache.needs_write(&removed, b"removed");
+        cache.finish().unwrap();
+
+        cache.start();
+        cache.needs_write(&kept, b"kept");
+        cache.finish().unwrap();
+
+        assert!(kept.exists());
+        assert!(!removed.exists());
+        assert_eq!(cache.changed(), &[removed.clone()]);
+        // Files the cache didn't write, like those of external renderers, are left alone
+        assert!(foreign.exists());
+    }
+
+    #[test]
+    fn it_reports_a_removed_page_once() {
+        let tmp = TempDir::new("mdbook").unwrap();
+        let page = tmp.path().join("chapter.html");
+        let mut cache = BuildCache::new();
+
+        cache.start();
+        assert!(!cache.is_up_to_date(&page, "# Chapter"));
+        cache.needs_write(&page, b"<h1>Chapter</h1>");
+        File::create(&page).unwrap();
+        cache.finish().unwrap();
+
+        cache.start();
+        cache.finish().unwrap();
+
+        assert!(!page.exists());
+        assert_eq!(cache.changed(), &[page.clone()]);
+    }
+}
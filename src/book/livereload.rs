@@ -0,0 +1,174 @@
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use errors::Error;
+use theme;
+
+/// The live reload of the pages of a book served by `mdbook serve`.
+///
+/// Every page gets a script that connects to the websocket at `url`, a path on the server of the
+/// page like `/__livereload` or a full url. After every build, the
+/// server sends a [`Message`](enum.Message.html) listing the files that changed, and each page
+/// decides what to do with it: replace its stylesheets, reload if it is affected, or nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveReload {
+    url: String,
+}
+
+impl LiveReload {
+    /// A live reload through the websocket at `url`, like `/__livereload` or `ws://localhost:3001`
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        LiveReload { url: url.into() }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The script to put in every page
+    pub fn script(&self) -> String {
+        format!("<script type=\"text/javascript\">\nvar livereload_url = {};\n{}</script>",
+                json!(self.url),
+                theme::LIVERELOAD_JS)
+    }
+}
+
+/// A message of the server to the pages, sent as JSON with its variant in a `type` field:
+///
+/// ```json
+/// {"type": "changed", "files": ["format/config.html", "book.css"]}
+/// {"type": "error", "message": "...", "causes": [], "file": "src/SUMMARY.md", "line": 3, "column": 1}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Message {
+    /// The files of the book, relative to its root and with `/` separators, that a build wrote or
+    /// removed
+    Changed { files: Vec<String> },
+    /// A build that failed, with the error and its causes. `file`, `line` and `column` locate the
+    /// error when they are known. The pages show it until the next successful build.
+    Error {
+        message: String,
+        causes: Vec<String>,
+        file: Option<String>,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+}
+
+impl Message {
+    /// The changes of a build, from the paths of `files`. Files outside of `dest`, the output
+    /// directory of the pages, are left out.
+    pub fn changed<P: AsRef<Path>>(dest: &Path, files: &[P]) -> Self {
+        let files = files.iter()
+                         .filter_map(|file| file.as_ref().strip_prefix(dest).ok())
+                         .map(|file| {
+                             file.components()
+                                 .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                                 .collect::<Vec<_>>()
+                                 .join("/")
+                         })
+                         .collect();
+
+        Message::Changed { files: files }
+    }
+
+    /// The failure of a build
+    pub fn error(error: &Error) -> Self {
+        let mut causes = vec![];
+        let mut cause = error.cause();
+        while let Some(e) = cause {
+            causes.push(e.to_string());
+            cause = e.cause();
+        }
+
+        let (file, line, column) = location(error);
+        Message::Error {
+            message: error.to_string(),
+            causes: causes,
+            file: file.map(|f| f.display().to_string()),
+            line: line,
+            column: column,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a message can always be serialized")
+    }
+}
+
+// The file, line and column of the deepest error of the chain that knows them
+fn location(error: &Error) -> (Option<PathBuf>, Option<usize>, Option<usize>) {
+    match *error {
+        Error::Summary { ref path, line, column, .. } => (Some(path.clone()), Some(line), Some(column)),
+        Error::MissingChapter { ref path } |
+        Error::Io { path: Some(ref path), .. } => (Some(path.clone()), None, None),
+        Error::Include { ref parent, ref cause } => {
+            match location(cause) {
+                (None, _, _) => (Some(parent.clone()), None, None),
+                location => location,
+            }
+        },
+        Error::Render { ref cause, .. } |
+        Error::Preprocessor { ref cause, .. } => location(cause),
+        _ => (None, None, None),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{LiveReload, Message};
+    use errors::Error;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn it_lists_the_changed_files_of_the_pages() {
+        let dest = Path::new("book/html");
+        let files = vec![PathBuf::from("book/html/format/config.html"),
+                         PathBuf::from("book/html/book.css"),
+                         PathBuf::from("book/epub/book.epub")];
+
+        let message = Message::changed(dest, &files);
+        assert_eq!(message,
+                   Message::Changed { files: vec!["format/config.html".to_owned(), "book.css".to_owned()] });
+        assert_eq!(message.to_json(),
+                   r#"{"type":"changed","files":["format/config.html","book.css"]}"#);
+    }
+
+    #[test]
+    fn it_sets_the_url_of_the_websocket() {
+        let script = LiveReload::new("ws://localhost:3001").script();
+        assert!(script.starts_with("<script type=\"text/javascript\">\n\
+                                    var livereload_url = \"ws://localhost:3001\";\n"));
+        assert!(script.ends_with("})();\n</script>"));
+    }
+
+    #[test]
+    fn it_locates_errors() {
+        let summary = Error::Summary {
+            path: PathBuf::from("src/SUMMARY.md"),
+            line: 3,
+            column: 1,
+            snippet: String::new(),
+            message: "Expected a link".to_owned(),
+        };
+        let error = Error::render("html", summary);
+
+        assert_eq!(Message::error(&error),
+                   Message::Error {
+                       message: "The html renderer failed".to_owned(),
+                       causes: vec!["src/SUMMARY.md:3:1: Expected a link".to_owned()],
+                       file: Some("src/SUMMARY.md".to_owned()),
+                       line: Some(3),
+                       column: Some(1),
+                   });
+
+        let error = Error::Other("Unknown preprocessor".to_owned());
+        assert_eq!(Message::error(&error).to_json(),
+                   concat!(r#"{"type":"error","message":"Unknown preprocessor","causes":[],"#,
+                           r#""file":null,"line":null,"column":null}"#));
+    }
+}
@@ -1,21 +1,28 @@
 pub mod bookitem;
 pub mod bookconfig;
+pub mod cache;
+pub mod livereload;
+pub mod preprocessor;
 
 pub mod bookconfig_test;
 
-pub use self::bookitem::{BookItem, BookItems};
-pub use self::bookconfig::BookConfig;
+pub use self::bookitem::{BookItem, BookItems, SectionNumber};
+pub use self::bookconfig::{BookConfig, ConfigError};
+pub use self::cache::BuildCache;
+pub use self::livereload::LiveReload;
+pub use self::preprocessor::Preprocessor;
 
+use std::cell::RefCell;
+use std::hash::Hash;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
-use std::error::Error;
-use std::io;
-use std::io::Write;
-use std::io::ErrorKind;
+use std::io::{Read, Write};
 use std::process::Command;
+use std::mem;
 
-use {theme, parse, utils};
-use renderer::{Renderer, HtmlHandlebars};
+use {theme, parse, utils, toml};
+use errors::{Error, Result};
+use renderer::{Renderer, HtmlHandlebars, CmdRenderer, EpubRenderer, MarkdownRenderer, LatexRenderer};
 
 pub struct MDBook {
     root: PathBuf,
@@ -28,9 +35,17 @@ pub struct MDBook {
     pub description: String,
 
     pub content: Vec<BookItem>,
-    renderer: Box<Renderer>,
+    renderers: Vec<Box<Renderer>>,
+    preprocessors: Vec<Box<Preprocessor>>,
+    config: BookConfig,
 
-    livereload: Option<String>,
+    livereload: Option<LiveReload>,
+
+    /// What the previous builds wrote, when building incrementally
+    cache: Option<RefCell<BuildCache>>,
+
+    /// The output directory of the renderer currently running, if any
+    renderer_dest: Option<PathBuf>,
 
     /// Should `mdbook build` create files referenced from SUMMARY.md if they
     /// don't exist
@@ -42,6 +57,9 @@ pub struct MDBook {
     /// false => http://site.com/page
     /// true => http://site.com/page.html
     pub page_extension: bool,
+
+    /// Number the chapters, like `2.3.1.`, in the table of contents and the titles
+    pub number_sections: bool,
 }
 
 impl MDBook {
@@ -86,11 +104,17 @@ impl MDBook {
             description: String::new(),
 
             content: vec![],
-            renderer: Box::new(HtmlHandlebars::new()),
+            renderers: vec![Box::new(HtmlHandlebars::new())],
+            preprocessors: vec![Box::new(preprocessor::LinkPreprocessor::new()),
+                                Box::new(preprocessor::PlaypenPreprocessor::new())],
+            config: BookConfig::new(root),
 
             livereload: None,
+            cache: None,
+            renderer_dest: None,
             create_missing: true,
             page_extension: false,
+            number_sections: true,
         }
     }
 
@@ -143,12 +167,12 @@ impl MDBook {
     /// It uses the paths given as source and output directories and adds a `SUMMARY.md` and a
     /// `chapter_1.md` to the source directory.
 
-    pub fn init(&mut self) -> Result<(), Box<Error>> {
+    pub fn init(&mut self) -> Result<()> {
 
         debug!("[fn]: init");
 
         if !self.root.exists() {
-            fs::create_dir_all(&self.root).unwrap();
+            try!(fs::create_dir_all(&self.root).map_err(|e| Error::io(&self.root, e)));
             info!("{:?} created", &self.root);
         }
 
@@ -156,12 +180,12 @@ impl MDBook {
 
             if !self.dest.exists() {
                 debug!("[*]: {:?} does not exist, trying to create directory", self.dest);
-                try!(fs::create_dir_all(&self.dest));
+                try!(fs::create_dir_all(&self.dest).map_err(|e| Error::io(&self.dest, e)));
             }
 
             if !self.src.exists() {
                 debug!("[*]: {:?} does not exist, trying to create directory", self.src);
-                try!(fs::create_dir_all(&self.src));
+                try!(fs::create_dir_all(&self.src).map_err(|e| Error::io(&self.src, e)));
             }
 
             let summary = self.src.join("SUMMARY.md");
@@ -170,14 +194,11 @@ impl MDBook {
 
                 // Summary does not exist, create it
 
-                debug!("[*]: {:?} does not exist, trying to create SUMMARY.md", self.src.join("SUMMARY.md"));
-                let mut f = try!(File::create(&self.src.join("SUMMARY.md")));
-
-                debug!("[*]: Writing to SUMMARY.md");
-
-                try!(writeln!(f, "# Summary"));
-                try!(writeln!(f, ""));
-                try!(writeln!(f, "- [Chapter 1](./chapter_1.md)"));
+                debug!("[*]: {:?} does not exist, trying to create SUMMARY.md", summary);
+                try!(File::create(&summary).and_then(|mut f| {
+                    debug!("[*]: Writing to SUMMARY.md");
+                    f.write_all(b"# Summary\n\n- [Chapter 1](./chapter_1.md)\n")
+                }).map_err(|e| Error::io(&summary, e)));
             }
         }
 
@@ -192,22 +213,19 @@ impl MDBook {
                 BookItem::Chapter(ref ch) |
                 BookItem::Affix(ref ch) => ch,
             };
-            if !ch.path.as_os_str().is_empty() {
+            if !ch.is_draft() {
                 let path = self.src.join(&ch.path);
                 let md = path.with_extension("md");
 
                 if !md.exists() && !path.exists() {
                     if !self.create_missing {
-                        return Err(format!(
-                            "'{}' referenced from SUMMARY.md does not exist.",
-                            md.to_string_lossy()).into());
+                        return Err(Error::MissingChapter { path: md });
                     }
                     debug!("[*]: {:?} does not exist, trying to create file", md);
-                    ::std::fs::create_dir_all(md.parent().unwrap())?;
-                    let mut f = File::create(md)?;
-
-                    // debug!("[*]: Writing to {:?}", path);
-                    writeln!(f, "# {}", ch.name)?;
+                    let dir = md.parent().unwrap();
+                    fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+                    File::create(&md).and_then(|mut f| writeln!(f, "# {}", ch.name))
+                                     .map_err(|e| Error::io(&md, e))?;
                 }
             }
         }
@@ -216,7 +234,7 @@ impl MDBook {
         Ok(())
     }
 
-    pub fn create_gitignore(&self) {
+    pub fn create_gitignore(&self) -> Result<()> {
         let gitignore = self.get_gitignore();
 
         if !gitignore.exists() {
@@ -225,7 +243,7 @@ impl MDBook {
             // Because of `src/book/mdbook.rs#L37-L39`, `dest` will always start with `root`. If it
             // is not, `strip_prefix` will return an Error.
             if !self.get_dest().starts_with(&self.root) {
-                return;
+                return Ok(());
             }
 
             let relative = self.get_dest()
@@ -236,105 +254,288 @@ impl MDBook {
 
             debug!("[*]: {:?} does not exist, trying to create .gitignore", gitignore);
 
-            let mut f = File::create(&gitignore).expect("Could not create file.");
-
-            debug!("[*]: Writing to .gitignore");
-
-            writeln!(f, "{}", relative).expect("Could not write to file.");
+            try!(File::create(&gitignore).and_then(|mut f| {
+                debug!("[*]: Writing to .gitignore");
+                writeln!(f, "{}", relative)
+            }).map_err(|e| Error::io(&gitignore, e)));
         }
+        Ok(())
     }
 
     /// The `build()` method is the one where everything happens. First it parses `SUMMARY.md` to
-    /// construct the book's structure in the form of a `Vec<BookItem>` and then calls `render()`
-    /// method of the current renderer.
+    /// construct the book's structure in the form of a `Vec<BookItem>` and loads the content of every
+    /// chapter. Then, for every renderer, it runs the preprocessors over a fresh copy of the chapters
+    /// and calls the `render()` method of the renderer.
+    ///
+    /// It is the renderers who generate all the output files. Each renderer writes into its own
+    /// directory, see [`get_renderer_dest()`](#method.get_renderer_dest).
     ///
-    /// It is the renderer who generates all the output files.
-    pub fn build(&mut self) -> Result<(), Box<Error>> {
+    /// The output directory is emptied first, unless the book is built incrementally, see
+    /// [`set_incremental()`](#method.set_incremental).
+    pub fn build(&mut self) -> Result<()> {
         debug!("[fn]: build");
 
         try!(self.init());
+        try!(self.load_chapters());
+
+        // Clean output directory, unless the cache knows what the previous builds left in it
+        let clean = match self.cache {
+            Some(ref cache) => {
+                let mut cache = cache.borrow_mut();
+                cache.start();
+                cache.is_empty()
+            },
+            None => true,
+        };
+        if clean {
+            try!(utils::fs::remove_dir_content(&self.dest));
+        }
+
+        let chapters = self.content.clone();
+        let result = self.run_renderers(&chapters);
+        self.content = chapters;
+
+        if let Some(ref cache) = self.cache {
+            let mut cache = cache.borrow_mut();
+            match result {
+                Ok(()) => try!(cache.finish()),
+                Err(_) => cache.clear(),
+            }
+        }
 
-        // Clean output directory
-        try!(utils::fs::remove_dir_content(&self.dest));
+        result
+    }
+
+    fn run_renderers(&mut self, chapters: &[BookItem]) -> Result<()> {
+        for i in 0..self.renderers.len() {
+            let name = self.renderers[i].name().to_owned();
+            debug!("[*]: Running the {} renderer", name);
+
+            self.content = chapters.to_vec();
+            try!(self.preprocess(&name));
+
+            self.renderer_dest = Some(self.get_renderer_dest(&name));
+            let result = self.renderers[i].render(self);
+            self.renderer_dest = None;
 
-        try!(self.renderer.render(&self));
+            if let Err(e) = result {
+                return Err(Error::render(name, e));
+            }
+        }
 
         Ok(())
     }
 
+    /// Builds the book incrementally: the next builds only render the pages whose inputs changed
+    /// and only write the files whose content changed, instead of starting from an empty output
+    /// directory. Used by `watch` and `serve`, which build the same book over and over.
+    pub fn set_incremental(&mut self, incremental: bool) -> &mut Self {
+        self.cache = if incremental {
+            Some(RefCell::new(BuildCache::new()))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Whether the page `filename` of the output directory, built from `inputs`, is the same as
+    /// the one of the previous build, in which case renderers can skip it. `inputs` should hold
+    /// everything the page depends on, like the content of the chapter and the templates.
+    ///
+    /// Always false when the book isn't built incrementally.
+    pub fn is_up_to_date<P: AsRef<Path>, H: Hash + ?Sized>(&self, filename: P, inputs: &H) -> bool {
+        match self.cache {
+            Some(ref cache) => cache.borrow_mut().is_up_to_date(&self.get_dest().join(filename), inputs),
+            None => false,
+        }
+    }
+
 
     pub fn get_gitignore(&self) -> PathBuf {
         self.root.join(".gitignore")
     }
 
-    pub fn copy_theme(&self) -> Result<(), Box<Error>> {
+    pub fn copy_theme(&self) -> Result<()> {
         debug!("[fn]: copy_theme");
 
         let theme_dir = self.src.join("theme");
 
         if !theme_dir.exists() {
             debug!("[*]: {:?} does not exist, trying to create directory", theme_dir);
-            try!(fs::create_dir(&theme_dir));
+            try!(fs::create_dir(&theme_dir).map_err(|e| Error::io(&theme_dir, e)));
         }
 
-        // index.hbs
-        let mut index = try!(File::create(&theme_dir.join("index.hbs")));
-        try!(index.write_all(theme::INDEX));
-
-        // book.css
-        let mut css = try!(File::create(&theme_dir.join("book.css")));
-        try!(css.write_all(theme::CSS));
-
-        // favicon.png
-        let mut favicon = try!(File::create(&theme_dir.join("favicon.png")));
-        try!(favicon.write_all(theme::FAVICON));
-
-        // book.js
-        let mut js = try!(File::create(&theme_dir.join("book.js")));
-        try!(js.write_all(theme::JS));
-
-        // highlight.css
-        let mut highlight_css = try!(File::create(&theme_dir.join("highlight.css")));
-        try!(highlight_css.write_all(theme::HIGHLIGHT_CSS));
+        let write = |name: &str, content: &[u8]| -> Result<()> {
+            let path = theme_dir.join(name);
+            File::create(&path).and_then(|mut f| f.write_all(content))
+                               .map_err(|e| Error::io(&path, e))
+        };
 
-        // highlight.js
-        let mut highlight_js = try!(File::create(&theme_dir.join("highlight.js")));
-        try!(highlight_js.write_all(theme::HIGHLIGHT_JS));
+        try!(write("index.hbs", theme::INDEX));
+        try!(write("book.css", theme::CSS));
+        try!(write("favicon.png", theme::FAVICON));
+        try!(write("book.js", theme::JS));
+        try!(write("highlight.css", theme::HIGHLIGHT_CSS));
+        try!(write("highlight.js", theme::HIGHLIGHT_JS));
 
         Ok(())
     }
 
-    pub fn write_file<P: AsRef<Path>>(&self, filename: P, content: &[u8]) -> Result<(), Box<Error>> {
+    /// The files written or removed by the last build, when building incrementally. Empty otherwise.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        match self.cache {
+            Some(ref cache) => cache.borrow().changed().to_vec(),
+            None => vec![],
+        }
+    }
+
+    /// Writes `content` to `filename` in the output directory. When building incrementally, files
+    /// whose content didn't change since the previous build are not written again.
+    pub fn write_file<P: AsRef<Path>>(&self, filename: P, content: &[u8]) -> Result<()> {
         let path = self.get_dest().join(filename);
+        if let Some(ref cache) = self.cache {
+            if !cache.borrow_mut().needs_write(&path, content) {
+                debug!("[*]: {:?} is unchanged", path);
+                return Ok(());
+            }
+        }
         try!(utils::fs::create_file(&path).and_then(|mut file| {
             file.write_all(content)
-        }).map_err(|e| {
-            io::Error::new(io::ErrorKind::Other, format!("Could not create {}: {}", path.display(), e))
-        }));
+        }).map_err(|e| Error::io(&path, e)));
+        Ok(())
+    }
+
+    /// Copies the files of the source directory, except those with an extension of `ext_blacklist`,
+    /// to the output directory. When building incrementally, unchanged files are not copied again.
+    pub fn copy_src_files(&self, ext_blacklist: &[&str]) -> Result<()> {
+        if self.cache.is_none() {
+            return utils::fs::copy_files_except_ext(&self.src, self.get_dest(), true, ext_blacklist);
+        }
+
+        self.copy_dir(&self.src, Path::new(""), ext_blacklist)
+    }
+
+    fn copy_dir(&self, dir: &Path, relative: &Path, ext_blacklist: &[&str]) -> Result<()> {
+        for entry in fs::read_dir(dir).map_err(|e| Error::io(dir, e))? {
+            let path = entry?.path();
+            let name = relative.join(path.file_name().expect("a file should have a file name..."));
+
+            if path.is_dir() {
+                // The output directory may be inside the source directory
+                if path != self.get_dest() {
+                    self.copy_dir(&path, &name, ext_blacklist)?;
+                }
+            } else if path.is_file() {
+                let excluded = path.extension()
+                                   .and_then(|ext| ext.to_str())
+                                   .map_or(false, |ext| ext_blacklist.contains(&ext));
+                if excluded {
+                    continue;
+                }
+
+                let mut content = vec![];
+                File::open(&path).and_then(|mut f| f.read_to_end(&mut content))
+                                 .map_err(|e| Error::io(&path, e))?;
+                self.write_file(&name, &content)?;
+            }
+        }
         Ok(())
     }
 
-    /// Parses the `book.json` file (if it exists) to extract the configuration parameters.
-    /// The `book.json` file should be in the root directory of the book.
+    /// Parses the `book.toml` or `book.json` file (if it exists) to extract the configuration parameters.
+    /// The file should be in the root directory of the book.
     /// The root directory is the one specified when creating a new `MDBook`
+    ///
+    /// Errors carry the path of the file and, when it is known, the line and column of the problem.
+    ///
+    /// Any key can be overridden by a `MDBOOK_<TABLE>__<KEY>` environment variable (see
+    /// [`BookConfig::set_env_overrides()`](bookconfig/struct.BookConfig.html#method.set_env_overrides))
+    /// or with [`set_config_override()`](#method.set_config_override).
 
-    pub fn read_config(mut self) -> Self {
+    pub fn read_config(mut self) -> Result<Self> {
 
-        let config = BookConfig::new(&self.root)
-                         .read_config(&self.root)
-                         .to_owned();
+        let mut config = BookConfig::new(&self.root);
 
-        self.title = config.title;
-        self.description = config.description;
-        self.author = config.author;
+        // The environment comes first, so that overrides set on the book win
+        config.set_env_overrides();
+        for &(ref key, ref value) in self.config.get_overrides() {
+            config.set_override(key, value.clone());
+        }
+
+        let config = try!(config.read_config(&self.root)).to_owned();
+
+        self.title = config.book.title.clone();
+        self.description = config.book.description.clone();
+        self.author = config.book.author.clone();
+
+        self.dest = config.get_dest().to_owned();
+        self.src = config.get_src().to_owned();
+        self.theme_path = config.get_theme_path().to_owned();
 
-        self.dest = config.dest;
-        self.src = config.src;
-        self.theme_path = config.theme_path;
+        self.create_missing = config.build.create_missing;
+        self.page_extension = config.build.page_extension;
+        self.number_sections = config.build.number_sections;
 
+        // External preprocessors declared with `[preprocessor.<name>] command = "..."`
+        for (name, table) in &config.preprocessor {
+            match table.as_table().and_then(|t| t.get("command")).and_then(|c| c.as_str()) {
+                Some(command) => {
+                    let preprocessor = preprocessor::CmdPreprocessor::new(name.clone(), command.to_owned());
+                    self.preprocessors.push(Box::new(preprocessor));
+                },
+                None => debug!("[*]: No command given for preprocessor '{}'", name),
+            }
+        }
+
+        // Renderers declared with `[output.<name>]`, the default html renderer is used otherwise
+        if !config.output.is_empty() {
+            self.renderers.clear();
+            for (name, table) in &config.output {
+                let command = table.as_table().and_then(|t| t.get("command")).and_then(|c| c.as_str());
+
+                match (name.as_str(), command) {
+                    (_, Some(command)) => {
+                        self.renderers.push(Box::new(CmdRenderer::new(name.clone(), command.to_owned())));
+                    },
+                    ("html", None) => self.renderers.push(Box::new(HtmlHandlebars::new())),
+                    ("epub", None) => self.renderers.push(Box::new(EpubRenderer::new())),
+                    ("markdown", None) => self.renderers.push(Box::new(MarkdownRenderer::new())),
+                    ("latex", None) => self.renderers.push(Box::new(LatexRenderer::new())),
+                    (_, None) => warn!("[*]: Unknown renderer '{}' in the configuration, ignoring it", name),
+                }
+            }
+        }
+
+        self.config = config;
+
+        Ok(self)
+    }
+
+    /// Sets the configuration key `key`, a dotted path like `"build.dest"`, to `value` on top of
+    /// `book.toml` and the environment. It takes effect when the configuration is read with
+    /// [`read_config()`](#method.read_config).
+    ///
+    /// ```no_run
+    /// # extern crate mdbook;
+    /// # extern crate toml;
+    /// # use mdbook::MDBook;
+    /// # use std::path::Path;
+    /// # fn main() {
+    /// let book = MDBook::new(Path::new("mybook"))
+    ///                .set_config_override("book.title", toml::Value::String("Draft".to_owned()))
+    ///                .read_config();
+    /// # }
+    /// ```
+    pub fn set_config_override(mut self, key: &str, value: toml::Value) -> Self {
+        self.config.set_override(key, value);
         self
     }
 
+    /// Returns the configuration read from `book.toml` or `book.json`
+    pub fn get_config(&self) -> &BookConfig {
+        &self.config
+    }
+
     /// You can change the default renderer to another one by using this method. The only requirement
     /// is for your renderer to implement the [Renderer trait](../../renderer/renderer/trait.Renderer.html)
     ///
@@ -356,33 +557,80 @@ impl MDBook {
     /// **note:** Don't forget to put your renderer in a `Box` before passing it to `set_renderer()`
 
     pub fn set_renderer(mut self, renderer: Box<Renderer>) -> Self {
-        self.renderer = renderer;
+        self.renderers = vec![renderer];
         self
     }
 
-    pub fn test(&mut self) -> Result<(), Box<Error>> {
+    /// Adds a renderer, to be run after the ones already registered. When a book has more than one
+    /// renderer, each of them writes into its own sub-directory of the output directory.
+    pub fn add_renderer(mut self, renderer: Box<Renderer>) -> Self {
+        self.renderers.push(renderer);
+        self
+    }
+
+    /// Registers a new preprocessor. Unless `book.toml` specifies the preprocessors to run with the
+    /// `build.preprocess` key, registered preprocessors run in the order they were added, after the
+    /// built-in ones.
+    ///
+    /// ```no_run
+    /// # extern crate mdbook;
+    /// # use mdbook::MDBook;
+    /// # use mdbook::book::preprocessor::PlaypenPreprocessor;
+    /// # use std::path::Path;
+    /// # fn main() {
+    /// let book = MDBook::new(Path::new("mybook"))
+    ///                .add_preprocessor(Box::new(PlaypenPreprocessor::new()));
+    /// # }
+    /// ```
+    pub fn add_preprocessor(mut self, preprocessor: Box<Preprocessor>) -> Self {
+        self.preprocessors.push(preprocessor);
+        self
+    }
+
+    /// Runs the code blocks of the chapters as tests with `rustdoc --test`. The chapters are
+    /// preprocessed first, so that the files they include are tested too.
+    pub fn test(&mut self) -> Result<()> {
         // read in the chapters
         try!(self.parse_summary());
+        try!(self.load_chapters());
+        try!(self.preprocess("test"));
+
+        // rustdoc reads the preprocessed chapters from a temporary copy
+        let tmp = try!(utils::fs::create_temp_dir("mdbook-test"));
+        let result = self.test_chapters(&tmp);
+        if let Err(e) = fs::remove_dir_all(&tmp) {
+            warn!("[*]: Could not remove the temporary copy of the chapters: {}", e);
+        }
+        result
+    }
+
+    fn test_chapters(&self, tmp: &Path) -> Result<()> {
         for item in self.iter() {
 
             if let BookItem::Chapter(ref ch) = *item {
-                if ch.path != PathBuf::new() {
+                if !ch.is_draft() {
 
-                    let path = self.get_src().join(&ch.path);
+                    let source = ch.source_path(self.get_src());
+                    let path = tmp.join(&ch.path).with_extension("md");
+                    try!(utils::fs::create_file(&path).and_then(|mut f| f.write_all(ch.content.as_bytes()))
+                                                      .map_err(|e| Error::io(&path, e)));
 
-                    println!("[*]: Testing file: {:?}", path);
+                    info!("[*]: Testing file: {:?}", source);
 
                     let output_result = Command::new("rustdoc")
                                             .arg(&path)
                                             .arg("--test")
                                             .output();
-                    let output = try!(output_result);
+                    let output = try!(output_result.map_err(|e| Error::io("rustdoc", e)));
 
                     if !output.status.success() {
-                        return Err(Box::new(io::Error::new(ErrorKind::Other, format!(
-                                        "{}\n{}",
-                                        String::from_utf8_lossy(&output.stdout),
-                                        String::from_utf8_lossy(&output.stderr)))) as Box<Error>);
+                        return Err(Error::Command {
+                            command: format!("rustdoc {} --test", source.display()),
+                            status: output.status,
+                            stderr: format!("{}\n{}",
+                                            String::from_utf8_lossy(&output.stdout),
+                                            String::from_utf8_lossy(&output.stderr)),
+                        });
                     }
                 }
             }
@@ -407,8 +655,28 @@ impl MDBook {
         self
     }
 
+    /// Returns the output directory. While a renderer is running, this is the directory that
+    /// renderer should write into.
     pub fn get_dest(&self) -> &Path {
-        &self.dest
+        match self.renderer_dest {
+            Some(ref dest) => dest,
+            None => &self.dest,
+        }
+    }
+
+    /// Whether the book has a renderer called `name`, like `"html"`
+    pub fn has_renderer(&self, name: &str) -> bool {
+        self.renderers.iter().any(|renderer| renderer.name() == name)
+    }
+
+    /// Returns the directory the renderer called `name` writes into: `dest/<name>` when the book has
+    /// several renderers, or directly `dest` when there is only one.
+    pub fn get_renderer_dest(&self, name: &str) -> PathBuf {
+        if self.renderers.len() > 1 {
+            self.dest.join(name)
+        } else {
+            self.dest.clone()
+        }
     }
 
     pub fn set_src(mut self, src: &Path) -> Self {
@@ -455,7 +723,8 @@ impl MDBook {
         &self.description
     }
 
-    pub fn set_livereload(&mut self, livereload: String) -> &mut Self {
+    /// Puts the live reload script in every page, see [`LiveReload`](livereload/struct.LiveReload.html)
+    pub fn set_livereload(&mut self, livereload: LiveReload) -> &mut Self {
         self.livereload = Some(livereload);
         self
     }
@@ -465,7 +734,7 @@ impl MDBook {
         self
     }
 
-    pub fn get_livereload(&self) -> Option<&String> {
+    pub fn get_livereload(&self) -> Option<&LiveReload> {
         self.livereload.as_ref()
     }
 
@@ -483,9 +752,81 @@ impl MDBook {
     }
 
     // Construct book
-    fn parse_summary(&mut self) -> Result<(), Box<Error>> {
+    fn parse_summary(&mut self) -> Result<()> {
         // When append becomes stable, use self.content.append() ...
         self.content = parse::construct_bookitems(&self.src.join("SUMMARY.md"))?;
+
+        if !self.number_sections {
+            bookitem::for_each_chapter_mut(&mut self.content, &mut |ch| {
+                ch.number = None;
+                Ok(())
+            })?;
+        }
         Ok(())
     }
+
+    // Read the markdown source of every chapter
+    fn load_chapters(&mut self) -> Result<()> {
+        debug!("[fn]: load_chapters");
+
+        let src = self.src.clone();
+        bookitem::for_each_chapter_mut(&mut self.content, &mut |ch| {
+            if ch.is_draft() {
+                return Ok(());
+            }
+
+            let path = ch.source_path(&src);
+            debug!("[*]: Reading file: {:?}", path);
+            ch.content = utils::fs::file_to_string(&path)?;
+            Ok(())
+        })
+    }
+
+    // Run the enabled preprocessors supporting `renderer` over the loaded chapters
+    fn preprocess(&mut self, renderer: &str) -> Result<()> {
+        debug!("[fn]: preprocess");
+
+        // Move the chapters out of the book, so that preprocessors can look at the book while
+        // modifying them
+        let mut items = mem::replace(&mut self.content, vec![]);
+        let result = self.run_preprocessors(renderer, &mut items);
+        self.content = items;
+
+        result
+    }
+
+    fn run_preprocessors(&self, renderer: &str, items: &mut Vec<BookItem>) -> Result<()> {
+        for preprocessor in self.enabled_preprocessors()? {
+            let supported = preprocessor.supports_renderer(self, renderer)
+                                        .map_err(|e| Error::preprocessor(preprocessor.name(), e))?;
+            if !supported {
+                debug!("[*]: Preprocessor {} does not support the {} renderer, skipping it",
+                       preprocessor.name(),
+                       renderer);
+                continue;
+            }
+
+            debug!("[*]: Running preprocessor: {}", preprocessor.name());
+            preprocessor.run(self, items).map_err(|e| Error::preprocessor(preprocessor.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the preprocessors to run, in order, according to the `build.preprocess` key of the
+    /// configuration
+    fn enabled_preprocessors(&self) -> Result<Vec<&Preprocessor>> {
+        let names = match self.config.build.preprocess {
+            Some(ref names) => names,
+            None => return Ok(self.preprocessors.iter().map(|p| &**p).collect()),
+        };
+
+        let mut enabled = Vec::with_capacity(names.len());
+        for name in names {
+            match self.preprocessors.iter().find(|p| p.name() == name) {
+                Some(p) => enabled.push(&**p),
+                None => return Err(Error::Other(format!("Unknown preprocessor '{}' in the configuration", name))),
+            }
+        }
+        Ok(enabled)
+    }
 }
@@ -0,0 +1,100 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json;
+
+use book::{MDBook, BookItem, BookConfig};
+use errors::{Error, Result};
+use utils;
+use super::Preprocessor;
+
+/// A preprocessor that runs an external program, declared in `book.toml` with
+///
+/// ```toml
+/// [preprocessor.name]
+/// command = "program --with arguments"
+/// ```
+///
+/// The program is run from the book's root directory. Before running it for a given renderer,
+/// mdBook calls `program supports <renderer>`, from the same directory: a non-zero exit status
+/// means the preprocessor doesn't support that renderer and it is skipped. A program that can't
+/// be started fails the build.
+///
+/// Otherwise the book is written as JSON to the program's standard input, with the following
+/// shape:
+///
+/// ```json
+/// {
+///     "root": "/path/to/book",
+///     "config": { "book": { "title": "...", ... }, "build": { ... }, "output": { ... }, ... },
+///     "items": [ { "type": "Chapter", "name": "...", "path": "...", "content": "...", "subItems": [...] } ]
+/// }
+/// ```
+///
+/// The program must print the (modified) list of items, in the same format as `items`, to its
+/// standard output.
+pub struct CmdPreprocessor {
+    name: String,
+    cmd: String,
+}
+
+#[derive(Serialize)]
+struct PreprocessorInput<'a> {
+    root: &'a Path,
+    config: &'a BookConfig,
+    items: &'a [BookItem],
+}
+
+impl CmdPreprocessor {
+    pub fn new(name: String, cmd: String) -> Self {
+        CmdPreprocessor {
+            name: name,
+            cmd: cmd,
+        }
+    }
+
+    fn command(&self) -> Result<Command> {
+        utils::cmd::command(&self.cmd, &format!("preprocessor '{}'", self.name))
+    }
+}
+
+impl Preprocessor for CmdPreprocessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn supports_renderer(&self, book: &MDBook, renderer: &str) -> Result<bool> {
+        debug!("[fn]: CmdPreprocessor::supports_renderer");
+
+        let output = self.command()?
+                         .arg("supports")
+                         .arg(renderer)
+                         .current_dir(book.get_root())
+                         .stdin(Stdio::null())
+                         .output()
+                         .map_err(|e| Error::io(&self.cmd, e))?;
+
+        Ok(output.status.success())
+    }
+
+    fn run(&self, book: &MDBook, items: &mut Vec<BookItem>) -> Result<()> {
+        debug!("[fn]: CmdPreprocessor::run");
+
+        let input = {
+            let input = PreprocessorInput {
+                root: book.get_root(),
+                config: book.get_config(),
+                items: items,
+            };
+            serde_json::to_vec(&input)?
+        };
+
+        let mut cmd = self.command()?;
+        cmd.current_dir(book.get_root()).stdout(Stdio::piped());
+        let output = utils::cmd::run(cmd, &self.cmd, &input)?;
+
+        *items = serde_json::from_slice(&output.stdout)?;
+
+        Ok(())
+    }
+}
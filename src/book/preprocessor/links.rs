@@ -0,0 +1,455 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::Read;
+
+use regex::Regex;
+
+use book::MDBook;
+use errors::{Error, Result};
+use book::bookitem::{self, BookItem};
+use super::Preprocessor;
+
+lazy_static! {
+    static ref LINK: Regex = Regex::new(r"\\?\{\{#(include|rustdoc_include)\s+([^}\s]+)\s*\}\}").unwrap();
+    // The `ANCHOR: name` and `ANCHOR_END: name` markers
+    static ref ANCHOR: Regex = Regex::new(r"ANCHOR(_END)?:\s*([\w-]+)").unwrap();
+}
+
+/// Replaces `{{#include file}}` links with the content of the file. A part of the file can be
+/// included with a line range, `{{#include file.rs:10:20}}`, or with the name of a region delimited
+/// by `ANCHOR: name` and `ANCHOR_END: name` comments, `{{#include file.rs:name}}`.
+///
+/// `{{#rustdoc_include file.rs:name}}` includes the whole file, but hides the lines outside of the
+/// range or the anchor from the readers with `# `, like in rustdoc. The example can still be run
+/// in the playpen and tested by `mdbook test`.
+///
+/// The included files can include other files, but not themselves. A missing or unreadable file, a
+/// missing anchor and a file including itself fail the build with an `Error::Include`. Like
+/// `{{#playpen}}`, a link can be escaped with a backslash: `\{{#include file}}`.
+#[derive(Default)]
+pub struct LinkPreprocessor;
+
+impl LinkPreprocessor {
+    pub fn new() -> Self {
+        LinkPreprocessor
+    }
+}
+
+impl Preprocessor for LinkPreprocessor {
+    fn name(&self) -> &str {
+        "links"
+    }
+
+    fn run(&self, book: &MDBook, items: &mut Vec<BookItem>) -> Result<()> {
+        debug!("[fn]: LinkPreprocessor::run");
+
+        bookitem::for_each_chapter_mut(items, &mut |ch| {
+            // Included paths are relative to the chapter's source file
+            let source = ch.source_path(book.get_src());
+            ch.content = try!(replace_all(&ch.content, &source));
+            Ok(())
+        })
+    }
+}
+
+/// Replaces the `{{#include}}` links in `s`, the content of the file `source`. Their paths are
+/// relative to `source`.
+pub fn replace_all(s: &str, source: &Path) -> Result<String> {
+    let dir = source.parent().unwrap_or_else(|| Path::new(""));
+    let mut included = vec![canonical(source)];
+    replace_links(s, dir, &mut included)
+}
+
+// Replaces the links in `s`, whose paths are relative to `path`. `included` is the chain of files
+// including `s`, from the chapter to the file of `s`.
+fn replace_links(s: &str, path: &Path, included: &mut Vec<PathBuf>) -> Result<String> {
+    let mut previous_end_index = 0;
+    let mut replaced = String::new();
+
+    for link in find_links(s, path) {
+        replaced.push_str(&s[previous_end_index..link.start_index]);
+        previous_end_index = link.end_index;
+
+        if link.escaped {
+            // Drop the backslash
+            replaced.push_str(&s[link.start_index + 1..link.end_index]);
+            continue;
+        }
+
+        let content = try!(link.render(included).map_err(|e| {
+            Error::Include {
+                parent: included.last().cloned().unwrap_or_default(),
+                cause: Box::new(e),
+            }
+        }));
+        replaced.push_str(&content);
+    }
+
+    replaced.push_str(&s[previous_end_index..]);
+    Ok(replaced)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum LinkType {
+    Include,
+    RustdocInclude,
+}
+
+#[derive(PartialEq, Debug)]
+enum LinkRange {
+    All,
+    /// The lines from the first to the second, 1-based and inclusive, or to the end of the file
+    Lines(usize, Option<usize>),
+    Anchor(String),
+}
+
+#[derive(PartialEq, Debug)]
+struct Link {
+    start_index: usize,
+    end_index: usize,
+    link_type: LinkType,
+    file: PathBuf,
+    range: LinkRange,
+    escaped: bool,
+}
+
+impl Link {
+    fn render(&self, included: &mut Vec<PathBuf>) -> Result<String> {
+        let file = canonical(&self.file);
+        if included.contains(&file) {
+            let cycle: Vec<_> = included.iter()
+                                        .chain(Some(&file))
+                                        .map(|p| p.display().to_string())
+                                        .collect();
+            return Err(Error::Other(format!("{} includes itself: {}", self.file.display(), cycle.join(" -> "))));
+        }
+
+        let mut content = String::new();
+        File::open(&self.file).and_then(|mut f| f.read_to_string(&mut content))
+                              .map_err(|e| Error::io(&self.file, e))?;
+
+        let content = match (self.link_type, &self.range) {
+            (LinkType::Include, &LinkRange::All) => Some(strip_anchors(&content)),
+            (LinkType::Include, &LinkRange::Lines(start, end)) => {
+                Some(strip_anchors(&take_lines(&content, start, end)))
+            },
+            (LinkType::Include, &LinkRange::Anchor(ref name)) => take_anchor(&content, name),
+            (LinkType::RustdocInclude, range) => hide_lines(&content, range),
+        };
+        // Only an anchor can be missing
+        let content = content.ok_or_else(|| {
+            let message = match self.range {
+                LinkRange::Anchor(ref name) => format!("No anchor '{}' in {}", name, self.file.display()),
+                _ => format!("Could not include {}", self.file.display()),
+            };
+            Error::Other(message)
+        })?;
+
+        let dir = self.file.parent().unwrap_or_else(|| Path::new(""));
+        included.push(file);
+        let content = replace_links(&content, dir, included);
+        included.pop();
+        content
+    }
+}
+
+// The same file can be reached by different paths, `a/../b.md` and `b.md`
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+fn find_links(s: &str, base_path: &Path) -> Vec<Link> {
+    let mut links = vec![];
+
+    for caps in LINK.captures_iter(s) {
+        let link = caps.get(0).unwrap();
+        let link_type = match &caps[1] {
+            "include" => LinkType::Include,
+            _ => LinkType::RustdocInclude,
+        };
+
+        if let Some((file, range)) = parse_link(&caps[2]) {
+            links.push(Link {
+                start_index: link.start(),
+                end_index: link.end(),
+                link_type: link_type,
+                file: base_path.join(file),
+                range: range,
+                escaped: link.as_str().starts_with('\\'),
+            });
+        }
+    }
+
+    links
+}
+
+// Splits `path:10:20` or `path:anchor` in the path and the part of the file to include
+fn parse_link(link: &str) -> Option<(&str, LinkRange)> {
+    let parts: Vec<&str> = link.split(':').collect();
+    // Line numbers are 1-based, an empty part is the start or the end of the file
+    let line = |part: &str, default: Option<usize>| {
+        match part.parse::<usize>() {
+            _ if part.is_empty() => Ok(default),
+            Ok(n) if n > 0 => Ok(Some(n)),
+            _ => Err(()),
+        }
+    };
+
+    let range = match parts.len() {
+        1 => LinkRange::All,
+        2 if parts[1].is_empty() => LinkRange::All,
+        2 => {
+            match line(parts[1], None) {
+                Ok(Some(n)) => LinkRange::Lines(n, Some(n)),
+                _ => LinkRange::Anchor(parts[1].to_owned()),
+            }
+        },
+        3 => {
+            match (line(parts[1], Some(1)), line(parts[2], None)) {
+                (Ok(Some(start)), Ok(end)) => LinkRange::Lines(start, end),
+                _ => return None,
+            }
+        },
+        _ => return None,
+    };
+
+    if parts[0].is_empty() {
+        None
+    } else {
+        Some((parts[0], range))
+    }
+}
+
+fn take_lines(s: &str, start: usize, end: Option<usize>) -> String {
+    let lines = s.lines().skip(start - 1);
+    let lines: Vec<&str> = match end {
+        Some(end) if end >= start => lines.take(end - start + 1).collect(),
+        Some(_) => vec![],
+        None => lines.collect(),
+    };
+    lines.join("\n")
+}
+
+// The lines between `ANCHOR: name` and `ANCHOR_END: name`, or to the end of the file when the
+// region isn't closed. `None` if the anchor doesn't exist.
+fn take_anchor(s: &str, name: &str) -> Option<String> {
+    let mut lines: Option<Vec<&str>> = None;
+
+    for line in s.lines() {
+        match (ANCHOR.captures(line), lines.is_some()) {
+            (Some(ref caps), false) if caps.get(1).is_none() && &caps[2] == name => lines = Some(vec![]),
+            (Some(ref caps), true) if caps.get(1).is_some() && &caps[2] == name => break,
+            // The markers of the other regions aren't part of the code
+            (Some(_), _) | (None, false) => {},
+            (None, true) => lines.as_mut().unwrap().push(line),
+        }
+    }
+
+    lines.map(|lines| lines.join("\n"))
+}
+
+// Removes the lines with anchor markers
+fn strip_anchors(s: &str) -> String {
+    if !ANCHOR.is_match(s) {
+        return s.to_owned();
+    }
+
+    s.lines().filter(|line| !ANCHOR.is_match(line)).collect::<Vec<_>>().join("\n")
+}
+
+// The whole file, with the lines outside of `range` hidden with `# `. `None` if the anchor doesn't
+// exist.
+fn hide_lines(s: &str, range: &LinkRange) -> Option<String> {
+    let mut lines = vec![];
+    let mut in_anchor = false;
+    let mut found = false;
+
+    for (i, line) in s.lines().enumerate() {
+        if let Some(caps) = ANCHOR.captures(line) {
+            if let LinkRange::Anchor(ref name) = *range {
+                if &caps[2] == name {
+                    in_anchor = caps.get(1).is_none();
+                    found = found || in_anchor;
+                }
+            }
+            continue;
+        }
+
+        let visible = match *range {
+            LinkRange::All => true,
+            LinkRange::Lines(start, end) => i + 1 >= start && end.map(|end| i < end).unwrap_or(true),
+            LinkRange::Anchor(_) => in_anchor,
+        };
+
+        if visible {
+            lines.push(line.to_owned());
+        } else if line.is_empty() {
+            lines.push("#".to_owned());
+        } else {
+            lines.push(format!("# {}", line));
+        }
+    }
+
+    match *range {
+        LinkRange::Anchor(_) if !found => None,
+        _ => Some(lines.join("\n")),
+    }
+}
+
+
+
+// ---------------------------------------------------------------------------------
+//      Tests
+//
+
+#[test]
+fn test_find_links_no_link() {
+    let s = "Some random text without link...";
+    assert!(find_links(s, Path::new("")) == vec![]);
+}
+
+#[test]
+fn test_find_links_partial_link() {
+    let s = "Some random text with {{#include...";
+    assert!(find_links(s, Path::new("")) == vec![]);
+}
+
+#[test]
+fn test_find_links_with_range() {
+    let s = "Some text {{#include file.rs}}, {{#include dir/file.rs:10:20}} and \\{{#include file.rs:anchor }}";
+
+    assert_eq!(find_links(s, Path::new("src")),
+               vec![Link {
+                        start_index: 10,
+                        end_index: 30,
+                        link_type: LinkType::Include,
+                        file: PathBuf::from("src/file.rs"),
+                        range: LinkRange::All,
+                        escaped: false,
+                    },
+                    Link {
+                        start_index: 32,
+                        end_index: 62,
+                        link_type: LinkType::Include,
+                        file: PathBuf::from("src/dir/file.rs"),
+                        range: LinkRange::Lines(10, Some(20)),
+                        escaped: false,
+                    },
+                    Link {
+                        start_index: 67,
+                        end_index: 96,
+                        link_type: LinkType::Include,
+                        file: PathBuf::from("src/file.rs"),
+                        range: LinkRange::Anchor("anchor".to_owned()),
+                        escaped: true,
+                    }]);
+}
+
+#[test]
+fn test_parse_link_ranges() {
+    assert_eq!(parse_link("file.rs:"), Some(("file.rs", LinkRange::All)));
+    assert_eq!(parse_link("file.rs:5"), Some(("file.rs", LinkRange::Lines(5, Some(5)))));
+    assert_eq!(parse_link("file.rs:5:"), Some(("file.rs", LinkRange::Lines(5, None))));
+    assert_eq!(parse_link("file.rs::5"), Some(("file.rs", LinkRange::Lines(1, Some(5)))));
+    assert_eq!(parse_link("file.rs:0:5"), None);
+    assert_eq!(parse_link("file.rs:a:b"), None);
+    assert_eq!(parse_link(":5"), None);
+}
+
+#[test]
+fn test_take_lines_and_anchors() {
+    let s = "fn main() {\n    // ANCHOR: body\n    let x = 1;\n    // ANCHOR: print\n    println!(\"{}\", x);\n    \
+             // ANCHOR_END: print\n    // ANCHOR_END: body\n}";
+
+    assert_eq!(take_lines(s, 1, Some(1)), "fn main() {");
+    assert_eq!(take_lines(s, 7, None), "    // ANCHOR_END: body\n}");
+    assert_eq!(take_anchor(s, "print"), Some("    println!(\"{}\", x);".to_owned()));
+    assert_eq!(take_anchor(s, "body"),
+               Some("    let x = 1;\n    println!(\"{}\", x);".to_owned()));
+    assert_eq!(take_anchor(s, "missing"), None);
+    assert_eq!(strip_anchors(s), "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}");
+}
+
+#[test]
+fn test_find_rustdoc_include() {
+    let s = "{{#rustdoc_include main.rs:body}}";
+
+    assert_eq!(find_links(s, Path::new("")),
+               vec![Link {
+                        start_index: 0,
+                        end_index: 33,
+                        link_type: LinkType::RustdocInclude,
+                        file: PathBuf::from("main.rs"),
+                        range: LinkRange::Anchor("body".to_owned()),
+                        escaped: false,
+                    }]);
+}
+
+#[test]
+fn test_hide_lines() {
+    let s = "fn main() {\n    // ANCHOR: body\n    let x = 1;\n\n    println!(\"{}\", x);\n    // ANCHOR_END: body\n}";
+
+    assert_eq!(hide_lines(s, &LinkRange::Anchor("body".to_owned())),
+               Some("# fn main() {\n    let x = 1;\n\n    println!(\"{}\", x);\n# }".to_owned()));
+    assert_eq!(hide_lines(s, &LinkRange::Lines(3, Some(3))),
+               Some("# fn main() {\n    let x = 1;\n#\n#     println!(\"{}\", x);\n# }".to_owned()));
+    assert_eq!(hide_lines(s, &LinkRange::Anchor("missing".to_owned())), None);
+}
+
+#[test]
+fn test_replace_all_escaped() {
+    let s = "Escaped \\{{#include file.rs}} and \\{{#include missing.rs}}";
+    assert_eq!(replace_all(s, Path::new("chapter.md")).unwrap(),
+               "Escaped {{#include file.rs}} and {{#include missing.rs}}");
+}
+
+#[test]
+fn test_replace_all_fails_on_missing_files() {
+    let chapter = Path::new("chapter.md");
+    match replace_all("Missing {{#include missing.rs}}", chapter) {
+        Err(Error::Include { parent, cause }) => {
+            assert_eq!(parent, chapter);
+            match *cause {
+                Error::Io { path: Some(ref path), .. } => assert_eq!(path, Path::new("missing.rs")),
+                ref e => panic!("Unexpected cause: {:?}", e),
+            }
+        },
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_replace_all_fails_on_include_cycles() {
+    use utils::testing::{temp_dir, write_files};
+
+    let tmp = temp_dir(&[("chapter.md", "Chapter {{#include chapter.md}}"), ("a.md", "A {{#include ./b.md}}")]);
+    let dir = tmp.path().file_name().unwrap().to_string_lossy().into_owned();
+    write_files(tmp.path(), &[("b.md", &*format!("B {{{{#include ../{}/a.md}}}}", dir))]);
+
+    let chapter = tmp.path().join("chapter.md");
+    match replace_all("Chapter {{#include chapter.md}}", &chapter) {
+        Err(Error::Include { cause, .. }) => assert!(cause.to_string().contains("includes itself")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // The error of b.md is in the error of a.md, in the error of the chapter
+    match replace_all("{{#include a.md}}", &chapter) {
+        Err(Error::Include { parent, cause }) => {
+            assert_eq!(parent, canonical(&chapter));
+            match *cause {
+                Error::Include { ref parent, ref cause } => {
+                    assert_eq!(parent, &canonical(&tmp.path().join("a.md")));
+                    match **cause {
+                        Error::Include { ref parent, ref cause } => {
+                            assert_eq!(parent, &canonical(&tmp.path().join("b.md")));
+                            assert!(cause.to_string().contains("a.md includes itself"));
+                        },
+                        ref e => panic!("Unexpected cause: {:?}", e),
+                    }
+                },
+                ref e => panic!("Unexpected cause: {:?}", e),
+            }
+        },
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,33 @@
+pub use self::links::LinkPreprocessor;
+pub use self::playpen::PlaypenPreprocessor;
+pub use self::cmd::CmdPreprocessor;
+
+pub mod links;
+pub mod playpen;
+pub mod cmd;
+
+use book::{MDBook, BookItem};
+use errors::Result;
+
+/// A `Preprocessor` transforms the raw markdown of the chapters after `SUMMARY.md` has been
+/// parsed and the chapter files have been loaded, but before any renderer sees the book.
+///
+/// Preprocessors run in the order given by the `build.preprocess` key of `book.toml`, or in the order
+/// they were registered with [`MDBook::add_preprocessor()`](../struct.MDBook.html#method.add_preprocessor)
+/// when that key is absent.
+pub trait Preprocessor {
+    /// The name used to refer to this preprocessor in `book.toml`
+    fn name(&self) -> &str;
+
+    /// Whether this preprocessor should run before the renderer called `renderer` of `book`.
+    /// Defaults to `true`. An error fails the build, like an error of `run()`.
+    fn supports_renderer(&self, _book: &MDBook, _renderer: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Transforms the chapters in `items` in place.
+    ///
+    /// `book` gives access to the paths and the configuration of the book. While the
+    /// preprocessors are running, the chapters live in `items` and not in `book`.
+    fn run(&self, book: &MDBook, items: &mut Vec<BookItem>) -> Result<()>;
+}
@@ -2,6 +2,41 @@ use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Read;
 
+use book::MDBook;
+use errors::Result;
+use book::bookitem::{self, BookItem};
+use super::Preprocessor;
+
+/// Replaces `{{#playpen file.rs}}` links with the content of the rust file, wrapped in a rust
+/// code block. `{{#playpen file.rs editable}}` makes the code block editable, like a
+/// ` ```rust,editable ` block.
+#[derive(Default)]
+pub struct PlaypenPreprocessor;
+
+impl PlaypenPreprocessor {
+    pub fn new() -> Self {
+        PlaypenPreprocessor
+    }
+}
+
+impl Preprocessor for PlaypenPreprocessor {
+    fn name(&self) -> &str {
+        "playpen"
+    }
+
+    fn run(&self, book: &MDBook, items: &mut Vec<BookItem>) -> Result<()> {
+        debug!("[fn]: PlaypenPreprocessor::run");
+
+        bookitem::for_each_chapter_mut(items, &mut |ch| {
+            // Playpen paths are relative to the chapter's source file
+            let source = ch.source_path(book.get_src());
+            if let Some(dir) = source.parent() {
+                ch.content = render_playpen(&ch.content, dir);
+            }
+            Ok(())
+        })
+    }
+}
 
 pub fn render_playpen(s: &str, path: &Path) -> String {
     // When replacing one thing in a string by something with a different length, the indices
@@ -35,8 +70,10 @@ pub fn render_playpen(s: &str, path: &Path) -> String {
             continue;
         };
 
-        let replacement = String::new() + "<pre><code class=\"language-rust\">" + &file_content +
-                          "</code></pre>";
+        let info = if playpen.editable { "rust,editable" } else { "rust" };
+        // Backticks in the code, in a doc comment for example, must not close the code block
+        let fence = "`".repeat(::std::cmp::max(3, longest_backtick_run(&file_content) + 1));
+        let replacement = format!("{fence}{}\n{}\n{fence}", info, file_content.trim_right(), fence = fence);
 
         replaced.push_str(&s[previous_end_index..playpen.start_index]);
         replaced.push_str(&replacement);
@@ -50,6 +87,10 @@ pub fn render_playpen(s: &str, path: &Path) -> String {
     replaced
 }
 
+fn longest_backtick_run(s: &str) -> usize {
+    s.split(|c| c != '`').map(|run| run.len()).max().unwrap_or(0)
+}
+
 #[derive(PartialOrd, PartialEq, Debug)]
 struct Playpen {
     start_index: usize,
@@ -193,3 +234,16 @@ fn test_find_playpens_escaped_playpen() {
         Playpen{start_index: 39, end_index: 68, rust_file: PathBuf::from("file.rs"), editable: true, escaped: true},
     ]);
 }
+
+#[test]
+fn test_render_playpen_editable() {
+    use utils::testing::temp_dir;
+
+    let tmp = temp_dir(&[("main.rs", "fn main() {}\n"),
+                         ("doc.rs", "/// ```\n/// assert!(true);\n/// ```\nfn main() {}\n")]);
+
+    assert_eq!(render_playpen("{{#playpen main.rs editable}}", tmp.path()),
+               "```rust,editable\nfn main() {}\n```");
+    assert_eq!(render_playpen("{{#playpen doc.rs}}", tmp.path()),
+               "````rust\n/// ```\n/// assert!(true);\n/// ```\nfn main() {}\n````");
+}
@@ -0,0 +1,271 @@
+//! The error type of mdBook
+//!
+//! Every fallible operation of the library returns an [`Error`](enum.Error.html). Errors that wrap
+//! another error, like the failure of a renderer, return it from `cause()`, so the whole chain can
+//! be reported:
+//!
+//! ```no_run
+//! # extern crate mdbook;
+//! # use mdbook::MDBook;
+//! # use std::error::Error;
+//! # use std::path::Path;
+//! # fn main() {
+//! let mut book = MDBook::new(Path::new("my-book"));
+//!
+//! if let Err(e) = book.build() {
+//!     println!("Error: {}", e);
+//!
+//!     let mut cause = e.cause();
+//!     while let Some(e) = cause {
+//!         println!("Caused by: {}", e);
+//!         cause = e.cause();
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::result;
+
+use handlebars::{TemplateError, RenderError};
+use serde_json;
+use zip::result::ZipError;
+
+use book::ConfigError;
+
+/// A specialized `Result` type for mdBook operations
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An invalid `book.toml` or `book.json`
+    Config(ConfigError),
+    /// An invalid `SUMMARY.md`. `line` and `column` are 1-based, `snippet` is the offending line.
+    Summary {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        snippet: String,
+        message: String,
+    },
+    /// An error in a file included by the file at `parent`, a summary or a chapter
+    Include {
+        parent: PathBuf,
+        cause: Box<Error>,
+    },
+    /// A chapter listed in `SUMMARY.md` whose file doesn't exist
+    MissingChapter { path: PathBuf },
+    /// A template of the theme that failed to compile or render
+    Template {
+        name: String,
+        cause: Box<StdError>,
+    },
+    /// A renderer that failed
+    Render {
+        renderer: String,
+        cause: Box<Error>,
+    },
+    /// A preprocessor that failed
+    Preprocessor {
+        name: String,
+        cause: Box<Error>,
+    },
+    /// An external program, like a renderer, a preprocessor or rustdoc, that exited with an error
+    Command {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// An I/O error, on `path` when it is known
+    Io {
+        path: Option<PathBuf>,
+        cause: io::Error,
+    },
+    /// Invalid JSON exchanged with an external renderer or preprocessor
+    Json(serde_json::Error),
+    /// Any other error
+    Other(String),
+}
+
+impl Error {
+    /// An I/O error on `path`
+    pub fn io<P: Into<PathBuf>>(path: P, cause: io::Error) -> Self {
+        Error::Io {
+            path: Some(path.into()),
+            cause: cause,
+        }
+    }
+
+    /// Wraps the error of the renderer `renderer`
+    pub fn render<S: Into<String>>(renderer: S, cause: Error) -> Self {
+        Error::Render {
+            renderer: renderer.into(),
+            cause: Box::new(cause),
+        }
+    }
+
+    /// Wraps the error of the preprocessor `name`
+    pub fn preprocessor<S: Into<String>>(name: S, cause: Error) -> Self {
+        Error::Preprocessor {
+            name: name.into(),
+            cause: Box::new(cause),
+        }
+    }
+
+    /// Wraps an error of the template `name`
+    pub fn template<S: Into<String>, E: StdError + 'static>(name: S, cause: E) -> Self {
+        Error::Template {
+            name: name.into(),
+            cause: Box::new(cause),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Config(ref e) => write!(f, "{}", e),
+            Error::Summary { ref path, line, column, ref snippet, ref message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)?;
+                if !snippet.is_empty() {
+                    // Points at the column, keeping the tabs so that it lines up
+                    let indent: String = snippet.chars()
+                                                .take(column - 1)
+                                                .map(|c| if c == '\t' { c } else { ' ' })
+                                                .collect();
+                    write!(f, "\n    {}\n    {}^", snippet, indent)?;
+                }
+                Ok(())
+            },
+            Error::Include { ref parent, .. } => write!(f, "Could not include a file in {}", parent.display()),
+            Error::MissingChapter { ref path } => write!(f, "The chapter {} doesn't exist", path.display()),
+            Error::Template { ref name, .. } => write!(f, "The template '{}' failed", name),
+            Error::Render { ref renderer, .. } => write!(f, "The {} renderer failed", renderer),
+            Error::Preprocessor { ref name, .. } => write!(f, "The preprocessor '{}' failed", name),
+            Error::Command { ref command, ref status, ref stderr } => {
+                write!(f, "'{}' failed ({})", command, status)?;
+                if !stderr.trim().is_empty() {
+                    write!(f, ":\n{}", stderr.trim_right())?;
+                }
+                Ok(())
+            },
+            Error::Io { path: Some(ref path), ref cause } => write!(f, "{}: {}", path.display(), cause),
+            Error::Io { path: None, ref cause } => write!(f, "{}", cause),
+            Error::Json(ref e) => write!(f, "Invalid JSON: {}", e),
+            Error::Other(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Config(ref e) => e.description(),
+            Error::Summary { ref message, .. } => message,
+            Error::Include { .. } => "include error",
+            Error::MissingChapter { .. } => "missing chapter",
+            Error::Template { .. } => "template error",
+            Error::Render { .. } => "renderer error",
+            Error::Preprocessor { .. } => "preprocessor error",
+            Error::Command { .. } => "command failed",
+            Error::Io { ref cause, .. } => cause.description(),
+            Error::Json(ref e) => e.description(),
+            Error::Other(ref message) => message,
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            Error::Template { ref cause, .. } => Some(&**cause),
+            Error::Include { ref cause, .. } |
+            Error::Render { ref cause, .. } |
+            Error::Preprocessor { ref cause, .. } => Some(&**cause),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io {
+            path: None,
+            cause: e,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(e: TemplateError) -> Self {
+        let name = e.template_name.clone().unwrap_or_default();
+        Error::template(name, e)
+    }
+}
+
+impl From<RenderError> for Error {
+    fn from(e: RenderError) -> Self {
+        let name = e.template_name.clone().unwrap_or_default();
+        Error::template(name, e)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(e: ZipError) -> Self {
+        match e {
+            ZipError::Io(e) => e.into(),
+            e => Error::Other(format!("Could not write the archive: {}", e)),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(message: &'a str) -> Self {
+        Error::Other(message.to_owned())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use std::error::Error as StdError;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_chains_the_causes() {
+        let missing = Error::MissingChapter { path: PathBuf::from("src/chapter_1.md") };
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "not found");
+        let error = Error::preprocessor("links", Error::io("src/intro.md", not_found));
+        let error = Error::render("html", error);
+
+        assert_eq!(missing.to_string(), "The chapter src/chapter_1.md doesn't exist");
+        assert_eq!(error.to_string(), "The html renderer failed");
+
+        let cause = error.cause().unwrap();
+        assert_eq!(cause.to_string(), "The preprocessor 'links' failed");
+        assert_eq!(cause.cause().unwrap().to_string(), "src/intro.md: not found");
+        assert!(cause.cause().unwrap().cause().is_none());
+    }
+}
@@ -24,10 +24,19 @@
 //! use std::path::Path;
 //!
 //! fn main() {
-//!     let mut book =  MDBook::new(Path::new("my-book"))   // Path to root
+//!     let book =  MDBook::new(Path::new("my-book"))       // Path to root
 //!                         .set_src(Path::new("src"))      // Path from root to source directory
 //!                         .set_dest(Path::new("book"))    // Path from root to output directory
-//!                         .read_config();                 // Parse book.json file for configuration
+//!                         .read_config();                 // Parse book.toml or book.json file for configuration
+//!
+//!     let mut book = match book {
+//!         Ok(book) => book,
+//!         Err(e) => {
+//!             // e.g. "my-book/book.toml:3:9: invalid type: integer `5`, expected a string"
+//!             println!("Invalid configuration: {}", e);
+//!             return;
+//!         },
+//!     };
 //!
 //!     book.build().unwrap();                              // Render the book
 //! }
@@ -58,6 +67,12 @@
 //!
 //! It's your responsability to create the necessary files in the correct directories.
 //!
+//! ## Errors
+//!
+//! All the fallible functions return an [`mdbook::errors::Error`](errors/enum.Error.html), which
+//! tells what failed (the configuration, `SUMMARY.md`, a renderer, ...) and gives access to the
+//! underlying causes.
+//!
 //! ## utils
 //!
 //! I have regrouped some useful functions in the [utils](utils/index.html) module, like the following function
@@ -69,8 +84,6 @@
 //!
 //! Make sure to take a look at it.
 
-#![feature(io)]
-
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -80,10 +93,18 @@ extern crate handlebars;
 extern crate pulldown_cmark;
 extern crate kramer;
 extern crate regex;
+#[macro_use]
+extern crate lazy_static;
+extern crate zip;
+extern crate rayon;
+extern crate toml;
+#[cfg(test)]
+extern crate tempdir;
 
 #[macro_use] extern crate log;
 pub mod book;
-mod parse;
+pub mod errors;
+pub mod parse;
 pub mod renderer;
 pub mod theme;
 pub mod utils;
@@ -91,4 +112,5 @@ pub mod utils;
 pub use book::MDBook;
 pub use book::BookItem;
 pub use book::BookConfig;
+pub use book::ConfigError;
 pub use renderer::Renderer;
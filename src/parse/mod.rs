@@ -1,3 +1,5 @@
-pub use self::summary::construct_bookitems;
+//! Parsers of the files that describe the book
+
+pub use self::summary::{construct_bookitems, parse_summary, Summary, SummaryItem, Link};
 
 pub mod summary;
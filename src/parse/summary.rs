@@ -1,266 +1,739 @@
+//! Parser for `SUMMARY.md`
+//!
+//! The summary is parsed as markdown, and only a few constructs are allowed, in this order:
+//!
+//! ```markdown
+//! # Summary
+//!
+//! [Introduction](introduction.md)
+//!
+//! - [First chapter](first.md)
+//!     - [Nested chapter](first/nested.md)
+//! - [Second chapter](second.md)
+//!
+//! ---------
+//!
+//! [Contributors](contributors.md)
+//! ```
+//!
+//! An optional title, prefix chapters written as plain links, numbered chapters in (nested) lists,
+//! and suffix chapters written as plain links again. Rules separate the chapters. Anything else
+//! is reported as an error with its position in the file.
+//!
+//! A link without a target, like `- [Future Work]()`, is a draft chapter that has no file yet.
+//!
+//! A list item can also include the chapters of another `SUMMARY.md`, at that position in the
+//! tree, with `- {{#summary path/to/SUMMARY.md}}`. Its paths are relative to its own directory.
+//! Linking to a directory that has a `SUMMARY.md` includes it under the directory's `index.md`.
+
 use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::io::{Read, Result, Error, ErrorKind};
-use book::bookitem::{BookItem, Chapter};
+use std::io::Read;
+
+use pulldown_cmark::{Parser, Event, Tag};
+
+use book::bookitem::{BookItem, Chapter, SectionNumber};
+use errors::{Error, Result};
+
+/// The parsed content of `SUMMARY.md`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// The title, the heading at the top of the file
+    pub title: Option<String>,
+    /// The unnumbered chapters before the numbered ones
+    pub prefix_chapters: Vec<SummaryItem>,
+    /// The chapters listed in the main list
+    pub numbered_chapters: Vec<SummaryItem>,
+    /// The unnumbered chapters after the numbered ones
+    pub suffix_chapters: Vec<SummaryItem>,
+}
 
+/// An entry of the summary
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummaryItem {
+    Link(Link),
+    /// A horizontal rule, `---`
+    Separator,
+    /// A `{{#summary path}}` directive, replaced by the items of another `SUMMARY.md`. The path is
+    /// relative to the directory of the summary containing the directive, and the offset is where
+    /// the directive starts in that summary.
+    Include(PathBuf, usize),
+}
+
+/// A link to a chapter, with its nested chapters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub name: String,
+    /// The target of the link, relative to the directory of `SUMMARY.md`
+    pub location: PathBuf,
+    pub nested_items: Vec<SummaryItem>,
+}
+
+impl Link {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(name: S, location: P) -> Self {
+        Link {
+            name: name.into(),
+            location: location.into(),
+            nested_items: vec![],
+        }
+    }
+}
+
+/// Reads and parses the `SUMMARY.md` at `path`, and the summaries it includes, into the items of
+/// the book
 pub fn construct_bookitems(path: &PathBuf) -> Result<Vec<BookItem>> {
     debug!("[fn]: construct_bookitems");
-    let mut summary = String::new();
-    File::open(path)?.read_to_string(&mut summary)?;
-
-    debug!("[*]: Parse SUMMARY.md");
-    let top_items = parse_level(path.parent().unwrap(), &mut summary.split('\n').collect(), 0)?;
+    let mut items = SummaryLoader::new().load(path)?;
+    number_chapters(&mut items, &[]);
     debug!("[*]: Done parsing SUMMARY.md");
-    Ok(top_items)
+    Ok(items)
+}
+
+/// Parses the text of a `SUMMARY.md`, `path` is only used in the errors
+pub fn parse_summary(path: &Path, text: &str) -> Result<Summary> {
+    SummaryParser::new(path, text).parse()
+}
+
+const NOT_A_LINK: &'static str = "Numbered chapters must be links, like `- [Title](path/to/chapter.md)`";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Prefix,
+    Numbered,
+    Suffix,
 }
 
-// TODO: Simplify SUMMARY.md. Name all chapters by the first header in their file. If a directory is given, go by dir/index.md.
-// TODO: Subsections have their own SUMMARY.md that gets read by the root one to generate the full TOC.
-// TODO: use a slice instead of popping from the front of a vector (eww, atl. use a deque).
-fn parse_level(path: &Path, summary: &mut Vec<&str>, current_level: i32) -> Result<Vec<BookItem>> {
-    debug!("[fn]: parse_level");
-    let mut items: Vec<BookItem> = vec![];
-
-    // Construct the book recursively
-    while !summary.is_empty() {
-        // Indentation level of the line to parse
-        let level = level(summary[0], 4)?;
-
-        // if level < current_level we remove the last digit of section, exit the current function,
-        // and return the parsed level to the calling function.
-        if level < current_level {
-            break;
+struct SummaryParser<'a> {
+    path: &'a Path,
+    text: &'a str,
+    stream: Parser<'a>,
+    // Where the last event returned by `next_event` starts
+    offset: usize,
+}
+
+impl<'a> SummaryParser<'a> {
+    fn new(path: &'a Path, text: &'a str) -> Self {
+        SummaryParser {
+            path: path,
+            text: text,
+            stream: Parser::new(text),
+            offset: 0,
         }
+    }
 
-        // if level > current_level we call ourselves to go one level deeper
-        if level > current_level {
-            debug!("[*]: Summary; parsing deeper at {}", level);
-            // Level can not be root level !!
-            let last = items.pop().expect("There should be at least one item since this can't be the root level");
-
-            if let BookItem::Chapter(mut ch) = last {
-                ch.sub_items.append(&mut parse_level(path, summary, level)?);
-                items.push(BookItem::Chapter(ch));
-                continue;
-            } else {
-                return Err(Error::new(ErrorKind::Other,
-                                      "Your summary.md is messed up\n\n
-                        Prefix, \
-                                       Suffix and Spacer elements can only exist on the root level.\n
-                        \
-                                       Prefix elements can only exist before any chapter and there can be \
-                                       no chapters after suffix elements."));
-            };
+    fn next_event(&mut self) -> Option<Event<'a>> {
+        self.offset = self.stream.get_offset();
+        self.stream.next()
+    }
 
-        } else {
-            // level and current_level are the same, parse the line
-            if let Some(parsed_item) = parse_line(path, summary[0]) {
-                // Eliminate possible errors and set section to -1 after suffix
-                let item = match parsed_item {
-                    // error if level != 0 and BookItem is != Chapter
-                    BookItem::Affix(_) | BookItem::Spacer if level > 0 => {
-                        return Err(Error::new(ErrorKind::Other,
-                                              "Your summary.md is messed up\n\n
-                        \
-                                       Prefix, Suffix and Spacer elements can only exist on the \
-                                       root level.\n
-                        Prefix \
-                                       elements can only exist before any chapter and there can be \
-                                       no chapters after suffix elements."))
-                    },
+    fn parse(mut self) -> Result<Summary> {
+        let mut summary = Summary::default();
+        let mut state = State::Prefix;
+        let mut first = true;
+
+        while let Some(event) = self.next_event() {
+            match event {
+                Event::Start(Tag::Header(1)) if first => {
+                    summary.title = Some(self.text_until_end()?);
+                },
+                Event::Start(Tag::Paragraph) => {
+                    let links = self.parse_affix()?;
+                    if state == State::Numbered {
+                        state = State::Suffix;
+                    }
+                    if state == State::Prefix {
+                        summary.prefix_chapters.extend(links);
+                    } else {
+                        summary.suffix_chapters.extend(links);
+                    }
+                },
+                Event::Start(Tag::List(_)) => {
+                    if state == State::Suffix {
+                        return Err(self.error("Numbered chapters can't come after the suffix chapters"));
+                    }
+                    state = State::Numbered;
+                    let items = self.parse_list()?;
+                    summary.numbered_chapters.extend(items);
+                },
+                Event::Start(Tag::Rule) => {
+                    match state {
+                        State::Prefix => summary.prefix_chapters.push(SummaryItem::Separator),
+                        State::Numbered => summary.numbered_chapters.push(SummaryItem::Separator),
+                        State::Suffix => summary.suffix_chapters.push(SummaryItem::Separator),
+                    }
+                },
+                Event::End(Tag::Rule) => {},
+                other => return Err(self.unexpected(&other)),
+            }
+            first = false;
+        }
 
-                    // error if BookItem == Chapter and section == -1
-                    // TODO: Make sure this can be safely removed. This should be an unreachable case.
-                    // BookItem::Chapter(_) if section[0] == -1 => {
-                    //     return Err(Error::new(ErrorKind::Other,
-                    //                           "Your summary.md is messed up\n\n
-                    //     \
-                    //                    Prefix, Suffix and Spacer elements can only exist on the \
-                    //                    root level.\n
-                    //     Prefix \
-                    //                    elements can only exist before any chapter and there can be \
-                    //                    no chapters after suffix elements."))
-                    // },
-
-                    x => x,
-                };
-
-                summary.remove(0);
-                items.push(item)
-            } else {
-                // If parse_line does not return Some(_) continue...
-                summary.remove(0);
-            };
+        Ok(summary)
+    }
+
+    // Prefix and suffix chapters, a paragraph of links
+    fn parse_affix(&mut self) -> Result<Vec<SummaryItem>> {
+        let mut links = vec![];
+
+        loop {
+            match self.next_event() {
+                Some(Event::Start(Tag::Link(dest, _))) => {
+                    let name = self.text_until_end()?;
+                    links.push(SummaryItem::Link(Link::new(name, &*dest)));
+                },
+                Some(Event::End(Tag::Paragraph)) => return Ok(links),
+                Some(Event::SoftBreak) |
+                Some(Event::HardBreak) => {},
+                Some(Event::Text(ref text)) if text.trim().is_empty() => {},
+                Some(Event::Text(_)) => {
+                    return Err(self.error("Unnumbered chapters must be links, like `[Title](path/to/chapter.md)`"))
+                },
+                Some(other) => return Err(self.unexpected(&other)),
+                None => return Err(self.error("Unexpected end of the file")),
+            }
         }
     }
-    debug!("[*]: Level: {:?}", items);
-    Ok(items)
-}
 
+    // Numbered chapters, a list of links that may contain nested lists
+    fn parse_list(&mut self) -> Result<Vec<SummaryItem>> {
+        let mut items = vec![];
 
-fn level(line: &str, spaces_in_tab: i32) -> Result<i32> {
-    debug!("[fn]: level");
-    let mut spaces = 0;
-    let mut level = 0;
+        loop {
+            match self.next_event() {
+                Some(Event::Start(Tag::Item)) => items.push(self.parse_item()?),
+                Some(Event::End(Tag::List(_))) => return Ok(items),
+                Some(other) => return Err(self.unexpected(&other)),
+                None => return Err(self.error("Unexpected end of the file")),
+            }
+        }
+    }
 
-    for ch in line.chars() {
-        match ch {
-            ' ' => spaces += 1,
-            '\t' => level += 1,
-            _ => break,
+    fn parse_item(&mut self) -> Result<SummaryItem> {
+        let mut link: Option<Link> = None;
+        // The text outside of a link, which can only be a `{{#summary}}` directive
+        let mut text = String::new();
+        let mut text_offset = 0;
+        // Where the item starts, for the errors about the item as a whole
+        let start = self.offset;
+
+        loop {
+            match self.next_event() {
+                Some(Event::Start(Tag::Link(dest, _))) => {
+                    if link.is_some() || !text.is_empty() {
+                        return Err(self.error("A list item can only link to one chapter"));
+                    }
+                    let name = self.text_until_end()?;
+                    link = Some(Link::new(name, &*dest));
+                },
+                Some(Event::Start(Tag::List(_))) => {
+                    let nested = self.parse_list()?;
+                    match link {
+                        Some(ref mut link) => link.nested_items.extend(nested),
+                        None => {
+                            self.offset = start;
+                            return Err(self.error("Nested chapters must belong to a chapter"));
+                        },
+                    }
+                },
+                Some(Event::End(Tag::Item)) => break,
+                // Items separated by blank lines have their content in paragraphs
+                Some(Event::Start(Tag::Paragraph)) |
+                Some(Event::End(Tag::Paragraph)) |
+                Some(Event::SoftBreak) |
+                Some(Event::HardBreak) => {},
+                Some(Event::Text(ref t)) if t.trim().is_empty() => {},
+                Some(Event::Text(ref t)) if link.is_none() => {
+                    if text.is_empty() {
+                        text_offset = self.offset;
+                    }
+                    text.push_str(t);
+                },
+                Some(Event::Text(_)) => return Err(self.error(NOT_A_LINK)),
+                Some(other) => return Err(self.unexpected(&other)),
+                None => return Err(self.error("Unexpected end of the file")),
+            }
         }
-        if spaces >= spaces_in_tab {
-            level += 1;
-            spaces = 0;
+
+        if let Some(link) = link {
+            return Ok(SummaryItem::Link(link));
+        }
+
+        if text.is_empty() {
+            self.offset = start;
+            return Err(self.error("Empty list item, numbered chapters must be links"));
+        }
+        match parse_include(&text) {
+            Some(path) => Ok(SummaryItem::Include(path, text_offset)),
+            None => {
+                self.offset = text_offset;
+                Err(self.error(NOT_A_LINK))
+            },
+        }
+    }
+
+    // The text until the end of the current tag, e.g. the name of a link or the title
+    fn text_until_end(&mut self) -> Result<String> {
+        let mut text = String::new();
+        let mut depth = 0;
+
+        loop {
+            match self.next_event() {
+                Some(Event::Start(_)) => depth += 1,
+                Some(Event::End(_)) if depth == 0 => return Ok(text.trim().to_owned()),
+                Some(Event::End(_)) => depth -= 1,
+                Some(Event::Text(t)) => text.push_str(&t),
+                Some(Event::SoftBreak) |
+                Some(Event::HardBreak) => text.push(' '),
+                Some(other) => return Err(self.unexpected(&other)),
+                None => return Err(self.error("Unexpected end of the file")),
+            }
         }
     }
 
-    // If there are spaces left, there is an indentation error
-    if spaces > 0 {
-        debug!("[SUMMARY.md]:");
-        debug!("\t[line]: {}", line);
-        debug!("[*]: There is an indentation error on this line. Indentation should be {} spaces", spaces_in_tab);
-        return Err(Error::new(ErrorKind::Other, format!("Indentation error on line:\n\n{}", line)));
+    fn unexpected(&self, event: &Event) -> Error {
+        let what = match *event {
+            Event::Start(Tag::Header(_)) => "heading, only the title of the summary can be a heading",
+            Event::Start(Tag::CodeBlock(_)) => "code block",
+            Event::Start(Tag::BlockQuote) => "block quote",
+            Event::Start(Tag::Table(_)) => "table",
+            Event::Start(Tag::Image(..)) => "image",
+            Event::Start(Tag::Link(..)) => "link, numbered chapters must be in a list",
+            Event::Start(Tag::FootnoteDefinition(_)) |
+            Event::FootnoteReference(_) => "footnote",
+            Event::Html(_) |
+            Event::InlineHtml(_) => "HTML",
+            Event::Text(_) => "text",
+            _ => "markdown",
+        };
+        self.error(&format!("Unexpected {}", what))
     }
 
-    Ok(level)
+    fn error(&self, message: &str) -> Error {
+        summary_error(self.path, self.text, self.offset, message)
+    }
 }
 
+// An error at `offset` in the text of the summary at `path`
+fn summary_error(path: &Path, text: &str, offset: usize, message: &str) -> Error {
+    // The offset may be on the whitespace before the construct
+    let rest = &text[offset..];
+    let offset = offset + (rest.len() - rest.trim_left().len());
+    let offset = ::std::cmp::min(offset, text.len());
+
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = text[offset..].find('\n').map(|i| offset + i).unwrap_or(text.len());
+
+    Error::Summary {
+        path: path.to_owned(),
+        line: text[..offset].matches('\n').count() + 1,
+        column: text[line_start..offset].chars().count() + 1,
+        snippet: text[line_start..line_end].trim_right().to_owned(),
+        message: message.to_owned(),
+    }
+}
 
-fn parse_line(root: &Path, l: &str) -> Option<BookItem> {
-    debug!("[fn]: parse_line");
+// Turns the summaries into book items, following the included summaries
+struct SummaryLoader {
+    // The canonical paths of the summaries being loaded, to detect the cycles
+    stack: Vec<PathBuf>,
+}
 
-    // Remove leading and trailing spaces or tabs
-    let line = l.trim_matches(|c: char| c == ' ' || c == '\t').trim();
+// A summary being loaded, for the errors of the items it lists
+struct Source<'a> {
+    path: &'a Path,
+    text: &'a str,
+}
 
-    // Spacers are "------"
-    if line.starts_with("--") {
-        debug!("[*]: Line is spacer");
-        return Some(BookItem::Spacer);
+impl SummaryLoader {
+    fn new() -> Self {
+        SummaryLoader { stack: vec![] }
     }
 
-    line.chars().nth(0).map(|c| {
-        match c {
-            // List item
-            '-' | '*' => {
-                debug!("[*]: Line is list element");
-                let line = line.split_at(1).1.trim();
-                let (name, path) = read_link(line).unwrap_or_else(move || {
-                    (String::new(), PathBuf::from(line))
-                });
+    fn load(&mut self, path: &Path) -> Result<Vec<BookItem>> {
+        let canonical = path.canonicalize().map_err(|e| Error::io(path, e))?;
 
-                // TODO: Read the file's first header for the chapter name.
-                let mut full_path = PathBuf::from(root.join(&path));
-                println!("looking for chapter {:?}", full_path);
-                let mut chap = Chapter::new(name.to_owned(), path.clone());
-                if full_path.is_dir() {
-                    // Directory, so use the index.md in that folder after parsing its SUMMARY.md for items.
-                    // NOTE: Assumes that the base chapter file is index.md.
-
-                    match construct_bookitems(&full_path.join("SUMMARY.md").into()) {
-                        Ok(mut items) => {
-                            for book_item in &mut items {
-                                book_item.prepend(&chap);
-                            }
-                            chap.sub_items = items;
-                        },
-                        Err(_) => (),
-                    }
+        let mut text = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut text))
+                        .map_err(|e| Error::io(path, e))?;
 
-                    chap.path.push("index.md");
-                    full_path.push("index.md");
+        debug!("[*]: Parse {}", path.display());
+        let summary = parse_summary(path, &text)?;
+        let source = Source {
+            path: path,
+            text: &text,
+        };
 
-                } else {
-                    // Simple file, use that.
-                    chap.path.set_extension("md");
-                    full_path.set_extension("md");
-                }
+        self.stack.push(canonical);
+        let mut items = self.book_items(&source, summary.prefix_chapters, false)?;
+        items.extend(self.book_items(&source, summary.numbered_chapters, true)?);
+        items.extend(self.book_items(&source, summary.suffix_chapters, false)?);
+        self.stack.pop();
+
+        Ok(items)
+    }
 
-                // Now we need to get the chapter name from the file.
-                if full_path.is_file() && chap.name.is_empty() {
-                    let file = File::open(full_path).unwrap();
-                    let mut heading = false;
-                    for c in file.chars() {
-                        if heading {
-                            match c {
-                                Ok(c) => match c {
-                                    '\n' => break,
-                                    c => chap.name.push(c),
-                                },
-                                _ => break
-                            }
-                        } else {
-                            match c {
-                                Ok(c) => match c {
-                                    '#' => heading = true,
-                                    _ => continue,
-                                },
-                                _ => break
-                            }
+    // The items of the summary at `location`, with their paths made relative to the directory of
+    // `source`. `offset` is the position of the `{{#summary}}` directive in `source`, if any.
+    fn include(&mut self, source: &Source, location: &Path, offset: Option<usize>) -> Result<Vec<BookItem>> {
+        let path = source.path.parent().unwrap().join(location);
+
+        if let Ok(canonical) = path.canonicalize() {
+            if self.stack.contains(&canonical) {
+                let cycle: Vec<_> = self.stack
+                                        .iter()
+                                        .chain(Some(&canonical))
+                                        .map(|p| p.display().to_string())
+                                        .collect();
+                let message = format!("{} includes itself: {}", location.display(), cycle.join(" -> "));
+                return Err(match offset {
+                    Some(offset) => summary_error(source.path, source.text, offset, &message),
+                    None => {
+                        Error::Include {
+                            parent: source.path.to_owned(),
+                            cause: Box::new(Error::Other(message)),
                         }
-                    }
-                }
-                chap.path.set_extension(""); // keep _all_ links bare.
+                    },
+                });
+            }
+        }
 
-                Some(BookItem::Chapter(chap))
+        let mut items = self.load(&path).map_err(|e| {
+            Error::Include {
+                parent: source.path.to_owned(),
+                cause: Box::new(e),
             }
-            // Non-list element
-//            '[' => {
-//                debug!("[*]: Line is a link element");
-//
-//                if let Some((name, path)) = read_link(line) {
-//                    return Some(BookItem::Affix(Chapter::new(name, path)));
-//                } else {
-//                    return None;
-//                }
-//            },
-            '#' => None,
-            _ => {
-                debug!("[*]: Line is a link/plain element");
-
-                if let Some((name, mut path)) = read_link(line) {
-                    path.set_extension(""); // keep _all_ links bare
-                    Some(BookItem::Affix(Chapter::new(name, path)))
-                } else {
-                    // TODO: genericize the item chapter code for affix chapters here.
-                    None
-                }
+        })?;
+
+        let dir = location.parent().unwrap_or_else(|| Path::new(""));
+        for item in &mut items {
+            item.prepend(dir);
+        }
+        Ok(items)
+    }
+
+    fn book_items(&mut self, source: &Source, items: Vec<SummaryItem>, numbered: bool) -> Result<Vec<BookItem>> {
+        let mut book_items = vec![];
+
+        for item in items {
+            match item {
+                SummaryItem::Link(link) => {
+                    let mut chapter = self.chapter(source, &link)?;
+                    let mut sub_items = self.book_items(source, link.nested_items, true)?;
+                    sub_items.append(&mut chapter.sub_items);
+                    chapter.sub_items = sub_items;
+
+                    if numbered {
+                        book_items.push(BookItem::Chapter(chapter));
+                    } else {
+                        book_items.push(BookItem::Affix(chapter));
+                    }
+                },
+                SummaryItem::Separator => book_items.push(BookItem::Spacer),
+                SummaryItem::Include(location, offset) => {
+                    book_items.extend(self.include(source, &location, Some(offset))?)
+                },
             }
         }
-    }).unwrap_or_default()
+
+        Ok(book_items)
+    }
+
+    // The chapter a link points to. A directory is a chapter backed by its `index.md`, whose
+    // sub-chapters are listed in its own `SUMMARY.md`. A link without a target is a draft.
+    fn chapter(&mut self, source: &Source, link: &Link) -> Result<Chapter> {
+        if link.location.as_os_str().is_empty() {
+            return Ok(Chapter::new(link.name.clone(), PathBuf::new()));
+        }
+
+        let mut chapter = Chapter::new(link.name.clone(), link.location.clone());
+        let mut full_path = source.path.parent().unwrap().join(&link.location);
+
+        if full_path.is_dir() {
+            if full_path.join("SUMMARY.md").is_file() {
+                chapter.sub_items = self.include(source, &link.location.join("SUMMARY.md"), None)?;
+            }
+
+            chapter.path.push("index.md");
+            full_path.push("index.md");
+        } else {
+            full_path.set_extension("md");
+        }
+
+        if chapter.name.is_empty() {
+            chapter.name = first_heading(&full_path).unwrap_or_default();
+        }
+        // keep _all_ links bare
+        chapter.path.set_extension("");
+
+        Ok(chapter)
+    }
 }
 
-fn read_link(line: &str) -> Option<(String, PathBuf)> {
-    let mut start_delimitor;
-    let mut end_delimitor;
+// Numbers the chapters in `items`, which are the sub-chapters of the chapter numbered `parent`.
+// The prefix and suffix chapters stay unnumbered.
+fn number_chapters(items: &mut [BookItem], parent: &[u32]) {
+    let mut n = 0;
+    for item in items {
+        if let BookItem::Chapter(ref mut ch) = *item {
+            n += 1;
+            let mut number = parent.to_vec();
+            number.push(n);
+            number_chapters(&mut ch.sub_items, &number);
+            ch.number = Some(SectionNumber(number));
+        }
+    }
+}
 
-    // In the future, support for list item that is not a link
-    // Not sure if I should error on line I can't parse or just ignore them...
-    if let Some(i) = line.find('[') {
-        start_delimitor = i;
-    } else {
-        debug!("[*]: '[' not found, this line is not a link. Ignoring...");
+// The path of a `{{#summary path}}` directive
+fn parse_include(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    if !text.starts_with("{{#summary") || !text.ends_with("}}") {
         return None;
     }
 
-    if let Some(i) = line[start_delimitor..].find("](") {
-        end_delimitor = start_delimitor + i;
+    let path = text["{{#summary".len()..text.len() - 2].trim();
+    if path.is_empty() || text["{{#summary".len()..].starts_with(|c: char| !c.is_whitespace()) {
+        None
     } else {
-        debug!("[*]: '](' not found, this line is not a link. Ignoring...");
+        Some(PathBuf::from(path))
+    }
+}
+
+// The text of the first heading of a markdown file, used for the chapters without a name
+fn first_heading(path: &Path) -> Option<String> {
+    let mut text = String::new();
+    if File::open(path).and_then(|mut f| f.read_to_string(&mut text)).is_err() {
         return None;
     }
 
-    let name = line[start_delimitor + 1..end_delimitor].to_owned();
+    text.lines()
+        .find(|line| line.starts_with('#'))
+        .map(|line| line.trim_left_matches('#').trim().to_owned())
+}
+
 
-    start_delimitor = end_delimitor + 1;
-    if let Some(i) = line[start_delimitor..].find(')') {
-        end_delimitor = start_delimitor + i;
-    } else {
-        debug!("[*]: ')' not found, this line is not a link. Ignoring...");
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::{parse_summary, parse_include, number_chapters, construct_bookitems, SummaryLoader, Source,
+                Summary, SummaryItem, Link};
+    use book::bookitem::{BookItem, Chapter, SectionNumber};
+    use errors::Error;
+    use std::path::{Path, PathBuf};
+    use utils::testing::{temp_dir, write_files};
+
+    fn parse(text: &str) -> Summary {
+        parse_summary(Path::new("src/SUMMARY.md"), text).unwrap()
     }
 
-    let path = PathBuf::from(line[start_delimitor + 1..end_delimitor].to_owned());
+    fn parse_error(text: &str) -> (usize, usize, String, String) {
+        match parse_summary(Path::new("src/SUMMARY.md"), text) {
+            Err(Error::Summary { line, column, snippet, message, .. }) => (line, column, snippet, message),
+            other => panic!("Expected a summary error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_parses_all_the_sections() {
+        let text = r#"# Summary
+
+[Introduction](misc/introduction.md)
+
+- [mdBook](README.md)
+- [Command Line Tool](cli/cli-tool.md)
+    - [init](cli/init.md)
+    - [build](cli/build.md)
+
+-----------
+
+[Contributors](misc/contributors.md)
+"#;
+
+        let mut cli = Link::new("Command Line Tool", "cli/cli-tool.md");
+        cli.nested_items = vec![SummaryItem::Link(Link::new("init", "cli/init.md")),
+                                SummaryItem::Link(Link::new("build", "cli/build.md"))];
+
+        let expected = Summary {
+            title: Some("Summary".to_owned()),
+            prefix_chapters: vec![SummaryItem::Link(Link::new("Introduction", "misc/introduction.md"))],
+            numbered_chapters: vec![SummaryItem::Link(Link::new("mdBook", "README.md")),
+                                    SummaryItem::Link(cli),
+                                    SummaryItem::Separator],
+            suffix_chapters: vec![SummaryItem::Link(Link::new("Contributors", "misc/contributors.md"))],
+        };
+
+        assert_eq!(parse(text), expected);
+    }
+
+    #[test]
+    fn it_accepts_any_list_indentation() {
+        let text = "* [First](first.md)\n  * [Nested](nested.md)\n* [Second `code`](second.md)\n";
+        let summary = parse(text);
+
+        assert_eq!(summary.title, None);
+        assert_eq!(summary.numbered_chapters.len(), 2);
+        match summary.numbered_chapters[0] {
+            SummaryItem::Link(ref link) => assert_eq!(link.nested_items.len(), 1),
+            _ => panic!("Expected a link"),
+        }
+        assert_eq!(summary.numbered_chapters[1],
+                   SummaryItem::Link(Link::new("Second code", "second.md")));
+    }
+
+    #[test]
+    fn it_reports_the_position_of_errors() {
+        let (line, _, snippet, message) = parse_error("# Summary\n\n- [First](first.md)\n    - Second\n");
+        assert_eq!(line, 4);
+        assert_eq!(snippet, "    - Second");
+        assert!(message.starts_with("Numbered chapters must be links"), message);
+
+        let (line, column, snippet, message) = parse_error("- [First](first.md)\n\n```\ncode\n```\n");
+        assert_eq!((line, column), (3, 1));
+        assert_eq!(snippet, "```");
+        assert_eq!(message, "Unexpected code block");
+
+        let (line, _, _, message) = parse_error("- [First](first.md)\n\n[Suffix](suffix.md)\n\n- [Last](last.md)\n");
+        assert_eq!(line, 5);
+        assert_eq!(message, "Numbered chapters can't come after the suffix chapters");
+    }
 
-    Some((name, path))
+    #[test]
+    fn it_numbers_the_chapters() {
+        let chapter = |name: &str| Chapter::new(name.to_owned(), PathBuf::from(name));
+
+        let mut second = chapter("second");
+        second.sub_items = vec![BookItem::Chapter(chapter("nested")), BookItem::Chapter(chapter("nested-2"))];
+        let mut items = vec![BookItem::Affix(chapter("intro")),
+                             BookItem::Chapter(chapter("first")),
+                             BookItem::Chapter(second),
+                             BookItem::Spacer,
+                             BookItem::Affix(chapter("contributors"))];
+
+        number_chapters(&mut items, &[]);
+
+        let numbers: Vec<_> = items.iter()
+                                   .map(|item| match *item {
+                                       BookItem::Chapter(ref ch) |
+                                       BookItem::Affix(ref ch) => ch.number.as_ref().map(|n| n.to_string()),
+                                       BookItem::Spacer => None,
+                                   })
+                                   .collect();
+        assert_eq!(numbers, vec![None, Some("1.".to_owned()), Some("2.".to_owned()), None, None]);
+
+        match items[2] {
+            BookItem::Chapter(ref ch) => match ch.sub_items[1] {
+                BookItem::Chapter(ref nested) => {
+                    assert_eq!(nested.number, Some(SectionNumber(vec![2, 2])));
+                    assert_eq!(nested.numbered_name(), "2.2. nested-2");
+                },
+                _ => panic!("Expected a chapter"),
+            },
+            _ => panic!("Expected a chapter"),
+        }
+    }
+
+    #[test]
+    fn it_parses_draft_chapters() {
+        let summary = parse("- [Written](written.md)\n- [Future Work]()\n    - [Later]()\n");
+
+        let mut future = Link::new("Future Work", "");
+        future.nested_items = vec![SummaryItem::Link(Link::new("Later", ""))];
+        assert_eq!(summary.numbered_chapters[1], SummaryItem::Link(future));
+
+        let source = Source {
+            path: Path::new("src/SUMMARY.md"),
+            text: "",
+        };
+        let items = SummaryLoader::new().book_items(&source, summary.numbered_chapters[1..].to_vec(), true)
+                                        .unwrap();
+        match items[0] {
+            BookItem::Chapter(ref ch) => {
+                assert!(ch.is_draft());
+                assert_eq!(ch.name, "Future Work");
+                assert_eq!(ch.sub_items.len(), 1);
+            },
+            _ => panic!("Expected a chapter"),
+        }
+    }
+
+    #[test]
+    fn it_parses_summary_includes() {
+        let text = r#"- [Part I](part-1/index.md)
+    - {{#summary part-1/SUMMARY.md}}
+- {{#summary part-2/SUMMARY.md}}
+"#;
+        let summary = parse(text);
+
+        match summary.numbered_chapters[0] {
+            SummaryItem::Link(ref link) => {
+                assert_eq!(link.location, PathBuf::from("part-1/index.md"));
+                assert_eq!(link.nested_items.len(), 1);
+                match link.nested_items[0] {
+                    SummaryItem::Include(ref path, _) => assert_eq!(path, Path::new("part-1/SUMMARY.md")),
+                    ref other => panic!("Expected an include, got {:?}", other),
+                }
+            },
+            ref other => panic!("Expected a link, got {:?}", other),
+        }
+        match summary.numbered_chapters[1] {
+            SummaryItem::Include(ref path, _) => assert_eq!(path, Path::new("part-2/SUMMARY.md")),
+            ref other => panic!("Expected an include, got {:?}", other),
+        }
+
+        assert_eq!(parse_include("{{#summary  a/b/SUMMARY.md }}"), Some(PathBuf::from("a/b/SUMMARY.md")));
+        assert_eq!(parse_include("{{#summary}}"), None);
+        assert_eq!(parse_include("{{#summarya.md}}"), None);
+        assert_eq!(parse_include("Some text"), None);
+    }
+
+    #[test]
+    fn it_loads_the_included_summaries() {
+        let tmp = temp_dir(&[("SUMMARY.md",
+                              "[Intro](intro.md)\n\n- [Part](part/index.md)\n    - {{#summary part/SUMMARY.md}}\n"),
+                             ("part/SUMMARY.md",
+                              "[Foreword](foreword.md)\n\n- [Chapter](chapter.md)\n- {{#summary deep/SUMMARY.md}}\n"),
+                             ("part/deep/SUMMARY.md", "- [Deep](deep.md)\n")]);
+
+        let items = construct_bookitems(&tmp.path().join("SUMMARY.md")).unwrap();
+        let paths: Vec<_> = items.iter()
+                                 .flat_map(|item| match *item {
+                                     BookItem::Chapter(ref ch) => ch.sub_items.clone(),
+                                     _ => vec![],
+                                 })
+                                 .map(|item| match item {
+                                     BookItem::Chapter(ch) |
+                                     BookItem::Affix(ch) => (ch.path, ch.number),
+                                     BookItem::Spacer => panic!("Unexpected spacer"),
+                                 })
+                                 .collect();
+
+        assert_eq!(paths,
+                   vec![(PathBuf::from("part/foreword"), None),
+                        (PathBuf::from("part/chapter"), Some(SectionNumber(vec![1, 1]))),
+                        (PathBuf::from("part/deep/deep"), Some(SectionNumber(vec![1, 2])))]);
+
+        // A summary including itself, through another one, is reported where the cycle closes
+        write_files(tmp.path(), &[("part/deep/SUMMARY.md", "- [Deep](deep.md)\n- {{#summary ../SUMMARY.md}}\n")]);
+        let error = match construct_bookitems(&tmp.path().join("SUMMARY.md")) {
+            Err(Error::Include { parent, cause }) => {
+                assert_eq!(parent, tmp.path().join("SUMMARY.md"));
+                match *cause {
+                    Error::Include { parent, cause } => {
+                        assert_eq!(parent, tmp.path().join("part/SUMMARY.md"));
+                        *cause
+                    },
+                    other => panic!("Expected an include error, got {:?}", other),
+                }
+            },
+            other => panic!("Expected an include error, got {:?}", other),
+        };
+        match error {
+            Error::Summary { path, line, snippet, message, .. } => {
+                assert_eq!(path, tmp.path().join("part/deep/SUMMARY.md"));
+                assert_eq!(line, 2);
+                assert_eq!(snippet, "- {{#summary ../SUMMARY.md}}");
+                assert!(message.starts_with("../SUMMARY.md includes itself"), message);
+            },
+            other => panic!("Expected a summary error, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,81 @@
+use renderer::Renderer;
+use book::{MDBook, BookItem, BookConfig};
+use errors::{Error, Result};
+use utils;
+
+use std::path::Path;
+use std::fs;
+use std::process::Stdio;
+
+use serde_json;
+
+/// A renderer that runs an external program, declared in `book.toml` with
+///
+/// ```toml
+/// [output.name]
+/// command = "mdbook-name --with arguments"
+/// ```
+///
+/// The program is run from the renderer's output directory and receives the render context as
+/// JSON on its standard input:
+///
+/// ```json
+/// {
+///     "root": "/path/to/book",
+///     "dest": "/path/to/book/book/name",
+///     "config": { "book": { "title": "...", ... }, "build": { ... }, "output": { ... }, ... },
+///     "items": [ { "type": "Chapter", "name": "...", "path": "...", "content": "...", "subItems": [...] } ]
+/// }
+/// ```
+///
+/// The chapters in `items` have already been preprocessed for this renderer. A non-zero exit
+/// status fails the build, with the program's standard error as message.
+pub struct CmdRenderer {
+    name: String,
+    cmd: String,
+}
+
+#[derive(Serialize)]
+struct RenderContext<'a> {
+    root: &'a Path,
+    dest: &'a Path,
+    config: &'a BookConfig,
+    items: &'a [BookItem],
+}
+
+impl CmdRenderer {
+    pub fn new(name: String, cmd: String) -> Self {
+        CmdRenderer {
+            name: name,
+            cmd: cmd,
+        }
+    }
+}
+
+impl Renderer for CmdRenderer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn render(&self, book: &MDBook) -> Result<()> {
+        debug!("[fn]: CmdRenderer::render");
+
+        let context = RenderContext {
+            root: book.get_root(),
+            dest: book.get_dest(),
+            config: book.get_config(),
+            items: &book.content,
+        };
+        let context = serde_json::to_vec(&context)?;
+
+        let mut cmd = utils::cmd::command(&self.cmd, &format!("renderer '{}'", self.name))?;
+
+        fs::create_dir_all(book.get_dest()).map_err(|e| Error::io(book.get_dest(), e))?;
+
+        info!("[*]: Running {}", self.cmd);
+        cmd.current_dir(book.get_dest()).stdout(Stdio::inherit());
+        utils::cmd::run(cmd, &self.cmd, &context)?;
+
+        Ok(())
+    }
+}
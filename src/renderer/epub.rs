@@ -0,0 +1,505 @@
+use renderer::Renderer;
+use renderer::html_handlebars::ChapterRenderer;
+use book::MDBook;
+use book::bookitem::{BookItem, Chapter};
+use errors::{Error, Result};
+use utils;
+use regex::{Regex, Captures};
+
+use std::path::{Path, PathBuf, Component};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::{ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+
+const CONTAINER: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+    <rootfiles>
+        <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>
+"#;
+
+const STYLESHEET: &'static str = r#"body { font-family: serif; line-height: 1.4; }
+pre { white-space: pre-wrap; font-size: 0.85em; }
+code { font-family: monospace; }
+img { max-width: 100%; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #999; padding: 0.2em 0.5em; }
+"#;
+
+/// Renders the book as an EPUB 3 file, `<title>.epub`, in the output directory.
+///
+/// Every chapter becomes an XHTML page, in the order of `SUMMARY.md`. The navigation document
+/// and the NCX (for older readers) follow the hierarchy of the chapters. The chapters are rendered
+/// like the html pages, so their headings have the same ids. Images referenced with
+/// relative paths are copied from the source directory into the EPUB, and links to other chapters
+/// (`other.md#section`) point to their pages.
+#[derive(Default)]
+pub struct EpubRenderer;
+
+impl EpubRenderer {
+    pub fn new() -> Self {
+        EpubRenderer
+    }
+}
+
+// A chapter rendered to XHTML
+struct Page<'a> {
+    chapter: &'a Chapter,
+    href: String,
+    xhtml: String,
+}
+
+impl Renderer for EpubRenderer {
+    fn name(&self) -> &str {
+        "epub"
+    }
+
+    fn render(&self, book: &MDBook) -> Result<()> {
+        debug!("[fn]: EpubRenderer::render");
+
+        let mut pages = vec![];
+        let mut images = BTreeSet::new();
+        let language = escape(&book.get_config().book.language);
+
+        for item in book.iter() {
+            let ch = match *item {
+                BookItem::Chapter(ref ch) |
+                BookItem::Affix(ref ch) => ch,
+                BookItem::Spacer => continue,
+            };
+            if ch.is_draft() {
+                continue;
+            }
+
+            let page = page_path(ch, book.get_src());
+            // Links to an anchor of the page are prefixed with its file name, like in the html pages
+            let filename = page.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+            let html = rewrite_links(&ChapterRenderer::new(filename).set_playpens(false).render(&ch.content));
+
+            // Images are looked up relative to the chapter's source file
+            let dir = page.parent().unwrap_or_else(|| Path::new("")).to_owned();
+            for image in find_images(&html) {
+                let image = utils::fs::normalize_path(&dir.join(image));
+                if book.get_src().join(&image).is_file() {
+                    images.insert(image);
+                } else {
+                    warn!("[*]: Image {:?} referenced from {:?} does not exist", image, ch.path);
+                }
+            }
+
+            let xhtml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+    <head>
+        <meta charset="UTF-8" />
+        <title>{title}</title>
+        <link rel="stylesheet" type="text/css" href="{path_to_root}stylesheet.css" />
+    </head>
+    <body>
+{content}
+    </body>
+</html>
+"#,
+                                language = language,
+                                title = escape(&ch.name),
+                                path_to_root = utils::fs::path_to_root(&page),
+                                content = to_xhtml(&html));
+
+            pages.push(Page {
+                chapter: ch,
+                href: href(&page),
+                xhtml: xhtml,
+            });
+        }
+
+        let path = book.get_dest().join(epub_filename(book.get_title()));
+        info!("[*] Creating {:?} ✓", path.display());
+
+        let file = utils::fs::create_file(&path).map_err(|e| Error::io(&path, e))?;
+        let mut zip = ZipWriter::new(file);
+        let deflated = FileOptions::default();
+
+        // The mimetype has to be the first file of the archive and it must not be compressed
+        zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored))?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER.as_bytes())?;
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(package_document(book, &pages, &images).as_bytes())?;
+
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(nav_document(book, &pages).as_bytes())?;
+
+        zip.start_file("OEBPS/toc.ncx", deflated)?;
+        zip.write_all(ncx_document(book, &pages).as_bytes())?;
+
+        zip.start_file("OEBPS/stylesheet.css", deflated)?;
+        zip.write_all(STYLESHEET.as_bytes())?;
+
+        for page in &pages {
+            zip.start_file(format!("OEBPS/{}", page.href), deflated)?;
+            zip.write_all(page.xhtml.as_bytes())?;
+        }
+
+        for image in &images {
+            let mut content = vec![];
+            let source = book.get_src().join(image);
+            File::open(&source).and_then(|mut f| f.read_to_end(&mut content))
+                               .map_err(|e| Error::io(source, e))?;
+
+            zip.start_file(format!("OEBPS/{}", href(image)), deflated)?;
+            zip.write_all(&content)?;
+        }
+
+        zip.finish()?;
+
+        Ok(())
+    }
+}
+
+// The package document lists every file of the EPUB and the reading order
+fn package_document(book: &MDBook, pages: &[Page], images: &BTreeSet<PathBuf>) -> String {
+    let mut metadata = String::new();
+    metadata.push_str(&format!("        <dc:identifier id=\"book-id\">urn:mdbook:{}</dc:identifier>\n",
+                               escape(&slugify(book.get_title()))));
+    metadata.push_str(&format!("        <dc:title>{}</dc:title>\n", escape(book.get_title())));
+    metadata.push_str(&format!("        <dc:language>{}</dc:language>\n",
+                               escape(&book.get_config().book.language)));
+    if !book.get_author().is_empty() {
+        metadata.push_str(&format!("        <dc:creator>{}</dc:creator>\n", escape(book.get_author())));
+    }
+    if !book.get_description().is_empty() {
+        metadata.push_str(&format!("        <dc:description>{}</dc:description>\n",
+                                   escape(book.get_description())));
+    }
+    metadata.push_str(&format!("        <meta property=\"dcterms:modified\">{}</meta>\n", timestamp()));
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        manifest.push_str(&format!("        <item id=\"page-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                                   i,
+                                   escape(&page.href)));
+        spine.push_str(&format!("        <itemref idref=\"page-{}\"/>\n", i));
+    }
+    for (i, image) in images.iter().enumerate() {
+        manifest.push_str(&format!("        <item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                                   i,
+                                   escape(&href(image)),
+                                   media_type(image)));
+    }
+
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}    </metadata>
+    <manifest>
+        <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+        <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+        <item id="stylesheet" href="stylesheet.css" media-type="text/css"/>
+{manifest}    </manifest>
+    <spine toc="ncx">
+{spine}    </spine>
+</package>
+"#,
+            metadata = metadata,
+            manifest = manifest,
+            spine = spine)
+}
+
+// The EPUB 3 navigation document, a nested list following the chapter hierarchy
+fn nav_document(book: &MDBook, pages: &[Page]) -> String {
+    let mut toc = String::new();
+    nav_items(&book.content, pages, &mut toc);
+
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+    <head>
+        <meta charset="UTF-8" />
+        <title>{title}</title>
+    </head>
+    <body>
+        <nav epub:type="toc" id="toc">
+            <h1>{title}</h1>
+            {toc}
+        </nav>
+    </body>
+</html>
+"#,
+            language = escape(&book.get_config().book.language),
+            title = escape(book.get_title()),
+            toc = toc)
+}
+
+fn nav_items(items: &[BookItem], pages: &[Page], out: &mut String) {
+    let chapters: Vec<&Chapter> = items.iter()
+                                       .filter_map(|item| match *item {
+                                           BookItem::Chapter(ref ch) |
+                                           BookItem::Affix(ref ch) => Some(ch),
+                                           BookItem::Spacer => None,
+                                       })
+                                       .collect();
+    if chapters.is_empty() {
+        return;
+    }
+
+    out.push_str("<ol>");
+    for ch in chapters {
+        match find_page(ch, pages) {
+            Some(page) => out.push_str(&format!("<li><a href=\"{}\">{}</a>", escape(&page.href), escape(&ch.name))),
+            None => out.push_str(&format!("<li><span>{}</span>", escape(&ch.name))),
+        }
+        nav_items(&ch.sub_items, pages, out);
+        out.push_str("</li>");
+    }
+    out.push_str("</ol>");
+}
+
+// The NCX navigation map, for EPUB 2 reading systems
+fn ncx_document(book: &MDBook, pages: &[Page]) -> String {
+    let mut nav_map = String::new();
+    let mut play_order = 0;
+    ncx_nav_points(&book.content, pages, &mut play_order, &mut nav_map);
+
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+    <head>
+        <meta name="dtb:uid" content="urn:mdbook:{uid}"/>
+    </head>
+    <docTitle>
+        <text>{title}</text>
+    </docTitle>
+    <navMap>
+{nav_map}    </navMap>
+</ncx>
+"#,
+            uid = escape(&slugify(book.get_title())),
+            title = escape(book.get_title()),
+            nav_map = nav_map)
+}
+
+fn ncx_nav_points(items: &[BookItem], pages: &[Page], play_order: &mut usize, out: &mut String) {
+    for item in items {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) |
+            BookItem::Affix(ref ch) => ch,
+            BookItem::Spacer => continue,
+        };
+
+        match find_page(ch, pages) {
+            Some(page) => {
+                *play_order += 1;
+                out.push_str(&format!("<navPoint id=\"nav-{order}\" playOrder=\"{order}\"><navLabel><text>{name}</text></navLabel><content src=\"{href}\"/>\n",
+                                      order = play_order,
+                                      name = escape(&ch.name),
+                                      href = escape(&page.href)));
+                ncx_nav_points(&ch.sub_items, pages, play_order, out);
+                out.push_str("</navPoint>\n");
+            },
+            // Chapters without a page can't be in the navigation map, keep their children
+            None => ncx_nav_points(&ch.sub_items, pages, play_order, out),
+        }
+    }
+}
+
+fn find_page<'a, 'b>(ch: &Chapter, pages: &'a [Page<'b>]) -> Option<&'a Page<'b>> {
+    if ch.is_draft() {
+        return None;
+    }
+    pages.iter().find(|page| page.chapter.path == ch.path)
+}
+
+// The path of the chapter's page in the EPUB, mirroring the source file
+fn page_path(ch: &Chapter, src: &Path) -> PathBuf {
+    let source = ch.source_path(src);
+    source.strip_prefix(src)
+          .map(|p| p.to_owned())
+          .unwrap_or_else(|_| ch.path.clone())
+          .with_extension("xhtml")
+}
+
+// Paths inside the EPUB always use forward slashes
+fn href(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The relative sources of the images in a chapter
+fn find_images(html: &str) -> Vec<String> {
+    lazy_static! {
+        static ref IMAGE: Regex = Regex::new(r#"<img[^>]*?\ssrc="([^"]+)""#).unwrap();
+    }
+
+    IMAGE.captures_iter(html)
+         .map(|caps| caps[1].to_owned())
+         .filter(|src| !src.contains("://") && !src.starts_with('/') && !src.starts_with("data:"))
+         .collect()
+}
+
+// Pages mirror the source files, so relative links to chapters only need the extension of the pages
+fn rewrite_links(html: &str) -> String {
+    lazy_static! {
+        static ref CHAPTER_LINK: Regex = Regex::new(r#"(<a[^>]*?\shref=")([^":#?]+)\.md(#[^"]*)?""#).unwrap();
+    }
+
+    CHAPTER_LINK.replace_all(html, |caps: &Captures| {
+        format!("{}{}.xhtml{}\"", &caps[1], &caps[2], caps.get(3).map(|m| m.as_str()).unwrap_or(""))
+    }).into_owned()
+}
+
+// The markdown renderer outputs HTML, EPUB requires well-formed XHTML
+fn to_xhtml(html: &str) -> String {
+    lazy_static! {
+        static ref VOID_ELEMENT: Regex =
+            Regex::new(r"<(area|base|br|col|embed|hr|img|input|link|meta|source|wbr)(\s[^>]*?)?\s*/?>").unwrap();
+    }
+
+    let xhtml = VOID_ELEMENT.replace_all(html, |caps: &Captures| {
+        format!("<{}{} />", &caps[1], caps.get(2).map(|m| m.as_str()).unwrap_or(""))
+    });
+
+    // XHTML only knows the XML entities
+    xhtml.replace("&nbsp;", "&#160;")
+}
+
+fn media_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+        Some(ref e) if e == "png" => "image/png",
+        Some(ref e) if e == "jpg" || e == "jpeg" => "image/jpeg",
+        Some(ref e) if e == "gif" => "image/gif",
+        Some(ref e) if e == "svg" => "image/svg+xml",
+        Some(ref e) if e == "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn epub_filename(title: &str) -> String {
+    let name: String = title.chars()
+                            .map(|c| match c {
+                                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                                c => c,
+                            })
+                            .collect();
+    let name = name.trim();
+
+    if name.is_empty() {
+        "book.epub".to_owned()
+    } else {
+        format!("{}.epub", name)
+    }
+}
+
+fn slugify(s: &str) -> String {
+    s.chars()
+     .filter_map(|c| if c.is_alphanumeric() {
+         Some(c.to_ascii_lowercase())
+     } else if c.is_whitespace() || c == '-' {
+         Some('-')
+     } else {
+         None
+     })
+     .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+}
+
+// The current time in the `CCYY-MM-DDThh:mm:ssZ` format required by `dcterms:modified`
+fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from the number of days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite_links;
+    use book::MDBook;
+    use std::fs::File;
+    use std::io::Read;
+    use utils::testing::temp_book;
+    use zip::ZipArchive;
+
+    #[test]
+    fn it_rewrites_links_between_chapters() {
+        assert_eq!(rewrite_links(r#"<a href="nested/other.md">Other</a>"#),
+                   r#"<a href="nested/other.xhtml">Other</a>"#);
+        assert_eq!(rewrite_links(r#"<a href="../intro.md#usage" title="Usage">"#),
+                   r#"<a href="../intro.xhtml#usage" title="Usage">"#);
+        assert_eq!(rewrite_links(r#"<a href="https://example.com/README.md">"#),
+                   r#"<a href="https://example.com/README.md">"#);
+        assert_eq!(rewrite_links(r#"<a href="notes.mdx">"#), r#"<a href="notes.mdx">"#);
+    }
+
+    #[test]
+    fn it_builds_an_epub() {
+        let tmp = temp_book("[book]\ntitle = \"Test\"\nlanguage = \"fr\"\n\n[output.epub]\n",
+                            "# Summary\n\n- [Intro](intro.md)\n    - [Other](nested/other.md)\n",
+                            &[("src/intro.md", "# Intro\n\nSee [the other chapter](nested/other.md#details).\n"),
+                              ("src/nested/other.md", "# Other\n\n## Details\n")]);
+
+        let mut book = MDBook::new(tmp.path()).read_config().unwrap();
+        book.build().unwrap();
+
+        let epub = File::open(tmp.path().join("book").join("Test.epub")).unwrap();
+        let mut epub = ZipArchive::new(epub).unwrap();
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            epub.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+
+        assert_eq!(read("mimetype"), "application/epub+zip");
+
+        let opf = read("OEBPS/content.opf");
+        assert!(opf.contains(r#"<item id="page-0" href="intro.xhtml" media-type="application/xhtml+xml"/>"#));
+        assert!(opf.contains(r#"<item id="page-1" href="nested/other.xhtml" media-type="application/xhtml+xml"/>"#));
+        assert!(opf.contains("<itemref idref=\"page-0\"/>\n        <itemref idref=\"page-1\"/>"));
+        assert!(opf.contains("<dc:language>fr</dc:language>"));
+
+        let intro = read("OEBPS/intro.xhtml");
+        assert!(intro.contains(r#"xml:lang="fr" lang="fr""#));
+        assert!(intro.contains(r#"href="nested/other.xhtml#details""#));
+
+        // The fragment of the link is the id of the heading in the target page
+        let other = read("OEBPS/nested/other.xhtml");
+        assert!(other.contains(r#"id="details""#), other);
+    }
+}
@@ -1,21 +1,27 @@
 use renderer::html_handlebars::helpers;
+use renderer::html_handlebars::markdown::ChapterRenderer;
+use renderer::html_handlebars::playpen::PlaypenConfig;
+use renderer::html_handlebars::search::{SearchConfig, SearchIndex};
 use renderer::Renderer;
 use book::MDBook;
-use book::bookitem::BookItem;
+use book::bookitem::{BookItem, Chapter};
+use errors::{Error, Result};
 use {utils, theme};
-use regex::{Regex, Captures};
 
-use std::ascii::AsciiExt;
 use std::path::{Path, PathBuf};
-use std::fs::{self, File};
-use std::error::Error;
-use std::io::{self, Read};
-use std::collections::HashMap;
+use std::fs;
+use std::result;
 
-use handlebars::Handlebars;
+use handlebars::{Handlebars, RenderError};
+use rayon::prelude::*;
 
 use serde_json;
 
+/// The content of the `404.html` page
+const NOT_FOUND: &'static str = "# Document not found (404)
+
+This URL is invalid, sorry. Try the search instead!
+";
 
 #[derive(Default)]
 pub struct HtmlHandlebars;
@@ -27,7 +33,11 @@ impl HtmlHandlebars {
 }
 
 impl Renderer for HtmlHandlebars {
-    fn render(&self, book: &MDBook) -> Result<(), Box<Error>> {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn render(&self, book: &MDBook) -> Result<()> {
         debug!("[fn]: render");
         let mut handlebars = Handlebars::new();
 
@@ -36,8 +46,10 @@ impl Renderer for HtmlHandlebars {
 
         // Register template
         debug!("[*]: Register handlebars template");
-        handlebars.register_template_string("index", try!(String::from_utf8(theme.index)))?;
-        handlebars.register_template_string("chapter", try!(String::from_utf8(theme.chapter)))?;
+        let index = String::from_utf8(theme.index).map_err(|e| Error::template("index", e))?;
+        let chapter = String::from_utf8(theme.chapter).map_err(|e| Error::template("chapter", e))?;
+        handlebars.register_template_string("index", &index).map_err(|e| Error::template("index", e))?;
+        handlebars.register_template_string("chapter", &chapter).map_err(|e| Error::template("chapter", e))?;
 
         // Register helpers
         debug!("[*]: Register handlebars helpers");
@@ -49,154 +61,123 @@ impl Renderer for HtmlHandlebars {
 
         let mut data = make_data(book)?;
 
-        // Print version
-        let mut print_content: String = String::new();
+        // Search index
+        let search_config = SearchConfig::from_book(book);
+        data.insert("search_enabled".to_owned(), json!(search_config.enable));
+
+        // Everything the pages depend on besides their content: the templates, the table of contents
+        // and the configuration. When it changes, all the pages are rendered again.
+        let page_inputs = (&index, &chapter, serde_json::to_string(&data)?);
 
         // Check if dest directory exists
         debug!("[*]: Check if destination directory exists");
-        if fs::create_dir_all(book.get_dest()).is_err() {
-            return Err(Box::new(io::Error::new(io::ErrorKind::Other,
-                                               "Unexpected error when constructing destination path")));
-        }
+        fs::create_dir_all(book.get_dest()).map_err(|e| Error::io(book.get_dest(), e))?;
 
         // Render the index file (optionally a title page)
         // TODO: Allow an index.md that's inserted as data.content
-        let index = handlebars.render("index", &data)?;
-        book.write_file(book.get_dest().join("index.html"), &index.into_bytes())?;
-
-
-        // Render a file for every entry in the book
-        // let mut index = true;
-        for item in book.iter() {
-
-            match *item {
-                BookItem::Chapter(ref ch) |
-                BookItem::Affix(ref ch) => {
-                    if ch.path != PathBuf::new() {
-
-                        let mut path = book.get_src().join(&ch.path);
-
-                        // path may be:
-                        // - Full link: [title](file.md)
-                        // - Partial link: [title](file)
-                        // - Full name: file.md
-                        // - Partial name: file
-
-                        // target of path may be:
-                        // - file.md
-                        // - directory (no .md)
-
-                        // presuming it's a file, the full file name.
-                        let md = path.with_extension("md");
-
-                        if md.is_file() {
-                            // Just use this file.
-                            path = md;
-                        } else if path.is_dir() {
-                            // read index.md
-                            path = path.join("index.md");
-                        } else {
-                            // It doesn't exist.
-                        }
-
-                        debug!("[*]: Opening file: {:?}", path);
-                        let mut f = File::open(&path)?;
-                        let mut content = String::new();
-
-                        debug!("[*]: Reading file");
-                        f.read_to_string(&mut content)?;
-
-                        // Parse for playpen links
-                        if let Some(p) = path.parent() {
-                            content = helpers::playpen::render_playpen(&content, p);
-                        }
-
-                        // TODO: Make rendering more generic, allowing multiple input formats.
-                        // Render markdown using the pulldown-cmark crate
-                        content = utils::render_markdown(&content);
-                        print_content.push_str(&content);
-
-                        // Update the context with data for this file
-                        // NOTE: Removes the extension. May or may not be wanted.
-                        let path = ch.path.with_extension("");
-                        let path = if book.page_extension {
-                            ch.path.to_str().unwrap()
-                        } else {
-                            path.to_str().unwrap()
-                        };
-                        // let path = path.to_str().ok_or_else(||
-                        //     io::Error::new(io::ErrorKind::Other, "Could not convert path to str"))?;
-                        let slug = ch.path.file_stem().ok_or_else(||
-                            io::Error::new(io::ErrorKind::Other, "Couldn't get file name"))?.to_str().unwrap();
-                        data.insert("path".to_owned(), json!(path));
-                        data.insert("slug".to_owned(), json!(slug));
-                        data.insert("content".to_owned(), json!(content));
-                        data.insert("chapter_title".to_owned(), json!(ch.name));
-                        data.insert("path_to_root".to_owned(), json!(utils::fs::path_to_root(&ch.path)));
-
-                        // Render the handlebars template with the data
-                        debug!("[*]: Render template");
-                        let rendered = handlebars.render("chapter", &data)?;
-
-                        let filename = Path::new(&ch.path).with_extension("html");
-
-                        // Do several kinds of post-processing
-                        let rendered = build_header_links(rendered, path);
-                        let rendered = fix_anchor_links(rendered, path);
-                        let rendered = fix_code_blocks(rendered);
-                        let rendered = add_playpen_pre(rendered);
-
-                        // Write to file
-                        info!("[*] Creating {:?} ✓", filename.display());
-                        try!(book.write_file(filename, &rendered.into_bytes()));
-
-                        // Create an index.html from the first element in SUMMARY.md
-                        // if index {
-                        //     debug!("[*]: index.html");
-                        //
-                        //     let mut content = String::new();
-                        //     let _source = try!(File::open(book.get_dest().join(&ch.path.with_extension("html"))))
-                        //         .read_to_string(&mut content);
-                        //
-                        //     // This could cause a problem when someone displays code containing <base href=...>
-                        //     // on the front page, however this case should be very very rare...
-                        //     content = content.lines()
-                        //         .filter(|line| !line.contains("<base href="))
-                        //         .collect::<Vec<&str>>()
-                        //         .join("\n");
-                        //
-                        //     try!(book.write_file("index.html", content.as_bytes()));
-                        //
-                        //     info!("[*] Creating index.html from {:?} ✓",
-                        //           book.get_dest().join(&ch.path.with_extension("html")));
-                        //     index = false;
-                        // }
-                    }
-                },
-                _ => {},
+        let rendered = handlebars.render("index", &data).map_err(|e| Error::template("index", e))?;
+        book.write_file(book.get_dest().join("index.html"), &rendered.into_bytes())?;
+
+        let chapters: Vec<&Chapter> = book.iter()
+                                          .filter_map(|item| match *item {
+                                              BookItem::Chapter(ref ch) |
+                                              BookItem::Affix(ref ch) if !ch.is_draft() => Some(ch),
+                                              _ => None,
+                                          })
+                                          .collect();
+
+        // The print version and the search index depend on all the chapters
+        let book_inputs = (&page_inputs,
+                           chapters.iter().map(|ch| (&ch.path, &ch.content)).collect::<Vec<_>>());
+
+        // Search index, as JSON and as a script for books opened from the filesystem. Both files are
+        // checked, so that both count as built.
+        let search_inputs = (&book_inputs, format!("{:?}", search_config));
+        let build_search_index = search_config.enable &&
+                                 !(book.is_up_to_date("searchindex.json", &search_inputs) &
+                                   book.is_up_to_date("searchindex.js", &search_inputs));
+
+        // Decide what has to be rendered for every chapter, this needs the book
+        let mut pages = Vec::with_capacity(chapters.len());
+        for ch in chapters.iter().cloned() {
+            let path = page_path(book, ch);
+            let filename = ch.path.with_extension("html");
+            let slug = ch.path.file_stem().ok_or_else(|| {
+                Error::Other(format!("Couldn't get the file name of {}", ch.path.display()))
+            })?.to_string_lossy().into_owned();
+
+            let up_to_date = book.is_up_to_date(&filename, &(&page_inputs, &path, &ch.content));
+            if up_to_date {
+                debug!("[*]: {:?} is up to date", filename);
             }
+
+            pages.push(ChapterPage {
+                chapter: ch,
+                path: path,
+                slug: slug,
+                filename: filename,
+                up_to_date: up_to_date,
+                indexed: build_search_index && !search_config.is_excluded(&ch.path),
+            });
         }
 
-        // Print version
+        // The chapters are rendered independently of each other on a thread pool, then written and
+        // indexed in the order of the book
+        let rendered: Vec<_> = pages.par_iter().map(|page| page.render(&handlebars, &data)).collect();
 
-        // Update the context with data for this file
-        data.insert("path".to_owned(), json!("print.md"));
-        data.insert("content".to_owned(), json!(print_content));
-        data.insert("path_to_root".to_owned(), json!(utils::fs::path_to_root(Path::new("print.md"))));
+        let mut search_index = SearchIndex::new(search_config.clone());
+        for (page, (content, rendered)) in pages.iter().zip(rendered) {
+            if let Some(rendered) = rendered {
+                let rendered = rendered.map_err(|e| Error::template("chapter", e))?;
+                info!("[*] Creating {:?} ✓", page.filename.display());
+                try!(book.write_file(&page.filename, &rendered.into_bytes()));
+            }
 
-        // Render the handlebars template with the data
-        debug!("[*]: Render template");
+            if let (true, Some(content)) = (page.indexed, content) {
+                search_index.add_chapter(&page.chapter.name, &page.path, &content);
+            }
+        }
+
+        // Print version, the ids of its headings are unique across all the chapters
+        if !book.is_up_to_date("print.html", &book_inputs) {
+            let mut print_renderer = ChapterRenderer::new("print.html");
+            let mut print_content: String = String::new();
+            for ch in &chapters {
+                print_content.push_str(&print_renderer.render(&ch.content));
+            }
+
+            // Update the context with data for this file
+            data.insert("path".to_owned(), json!("print.md"));
+            data.insert("content".to_owned(), json!(print_content));
+            data.insert("path_to_root".to_owned(), json!(utils::fs::path_to_root(Path::new("print.md"))));
 
-        let rendered = try!(handlebars.render("index", &data));
+            // Render the handlebars template with the data
+            debug!("[*]: Render template");
 
-        // do several kinds of post-processing
-        let rendered = build_header_links(rendered, "print.html");
-        let rendered = fix_anchor_links(rendered, "print.html");
-        let rendered = fix_code_blocks(rendered);
-        let rendered = add_playpen_pre(rendered);
+            let rendered = try!(handlebars.render("index", &data).map_err(|e| Error::template("index", e)));
 
-        try!(book.write_file(Path::new("print").with_extension("html"), &rendered.into_bytes()));
-        info!("[*] Creating print.html ✓");
+            try!(book.write_file(Path::new("print").with_extension("html"), &rendered.into_bytes()));
+            info!("[*] Creating print.html ✓");
+        }
+
+        // Page for the missing pages, servers show it at any path so its links start from the url of
+        // the book
+        if has_not_found_page(book) {
+            let not_found = ChapterRenderer::new("404.html").render(NOT_FOUND);
+            data.insert("path".to_owned(), json!("404.md"));
+            data.insert("content".to_owned(), json!(not_found));
+            data.insert("path_to_root".to_owned(), json!(site_url(book)));
+
+            let rendered = try!(handlebars.render("index", &data).map_err(|e| Error::template("index", e)));
+            try!(book.write_file("404.html", &rendered.into_bytes()));
+        }
+
+        if build_search_index {
+            let index = search_index.to_json()?;
+            try!(book.write_file("searchindex.json", index.as_bytes()));
+            try!(book.write_file("searchindex.js", format!("window.searchindex = {};", index).as_bytes()));
+            info!("[*] Creating searchindex.json ✓");
+        }
 
         // Copy static files (js, css, images, ...)
 
@@ -217,154 +198,118 @@ impl Renderer for HtmlHandlebars {
         try!(book.write_file("_FontAwesome/fonts/FontAwesome.ttf", theme::FONT_AWESOME_TTF));
 
         // Copy all remaining files
-        try!(utils::fs::copy_files_except_ext(book.get_src(), book.get_dest(), true, &["md"]));
+        try!(book.copy_src_files(&["md"]));
 
         Ok(())
     }
 }
 
-fn make_data(book: &MDBook) -> Result<serde_json::Map<String, serde_json::Value>, Box<Error>> {
-    debug!("[fn]: make_data");
-
-    let mut data = serde_json::Map::new();
-    data.insert("language".to_owned(), json!("en"));
-    data.insert("title".to_owned(), json!(book.get_title()));
-    data.insert("description".to_owned(), json!(book.get_description()));
-    data.insert("favicon".to_owned(), json!("favicon.png"));
-    if let Some(livereload) = book.get_livereload() {
-        data.insert("livereload".to_owned(), json!(livereload));
-    }
-
-    // NOTE: Simply use the serde default serialization. Why not?
-    let chapters: Vec<_> = book.iter().collect();
-    println!("inserting chap data: {:?}", json!(chapters));
-    data.insert("chapters".to_owned(), json!(chapters));
-
-    debug!("[*]: JSON constructed");
-    Ok(data)
+/// A chapter to render, with what was decided for it while the book was at hand
+struct ChapterPage<'a> {
+    chapter: &'a Chapter,
+    path: String,
+    slug: String,
+    filename: PathBuf,
+    /// The page of the chapter is the same as in the previous build
+    up_to_date: bool,
+    /// The chapter goes into the search index
+    indexed: bool,
 }
 
-fn build_header_links(html: String, filename: &str) -> String {
-    let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
-    let mut id_counter = HashMap::new();
-
-    regex.replace_all(&html, |caps: &Captures| {
-        let level = &caps[1];
-        let text = &caps[2];
-        let mut id = text.to_string();
-        let repl_sub = vec!["<em>", "</em>", "<code>", "</code>",
-                            "<strong>", "</strong>",
-                            "&lt;", "&gt;", "&amp;", "&#39;", "&quot;"];
-        for sub in repl_sub {
-            id = id.replace(sub, "");
+impl<'a> ChapterPage<'a> {
+    /// Renders the content of the chapter when it is needed, and its page when it isn't up to date.
+    /// Chapters are rendered on several threads, so this only looks at `data`, which is left as it is.
+    fn render(&self,
+              handlebars: &Handlebars,
+              data: &serde_json::Map<String, serde_json::Value>)
+              -> (Option<String>, Option<result::Result<String, RenderError>>) {
+        if self.up_to_date && !self.indexed {
+            return (None, None);
         }
-        let id = id.chars().filter_map(|c| {
-            if c.is_alphanumeric() || c == '-' || c == '_' {
-                if c.is_ascii() {
-                    Some(c.to_ascii_lowercase())
-                } else {
-                    Some(c)
-                }
-            } else if c.is_whitespace() && c.is_ascii() {
-                Some('-')
-            } else {
-                None
-            }
-        }).collect::<String>();
 
-        let id_count = *id_counter.get(&id).unwrap_or(&0);
-        id_counter.insert(id.clone(), id_count + 1);
+        // TODO: Make rendering more generic, allowing multiple input formats.
+        // The chapter content has already been loaded and preprocessed, render
+        // the markdown
+        let ch = self.chapter;
+        let content = ChapterRenderer::new(&*self.path).render(&ch.content);
+        if self.up_to_date {
+            return (Some(content), None);
+        }
 
-        let id = if id_count > 0 {
-            format!("{}-{}", id, id_count)
-        } else {
-            id
-        };
+        // Update the context with data for this file
+        let mut data = data.clone();
+        data.insert("path".to_owned(), json!(self.path));
+        data.insert("slug".to_owned(), json!(self.slug));
+        data.insert("content".to_owned(), json!(content));
+        data.insert("chapter_title".to_owned(), json!(ch.numbered_name()));
+        data.insert("section".to_owned(), json!(ch.number.as_ref().map(|n| n.to_string())));
+        data.insert("path_to_root".to_owned(), json!(utils::fs::path_to_root(&ch.path)));
 
-        format!("<a class=\"header\" href=\"{filename}#{id}\" id=\"{id}\"><h{level}>{text}</h{level}></a>",
-            level=level, id=id, text=text, filename=filename)
-    }).into_owned()
+        // Render the handlebars template with the data
+        debug!("[*]: Render template");
+        let rendered = handlebars.render("chapter", &data);
+        (Some(content), Some(rendered))
+    }
 }
 
-// anchors to the same page (href="#anchor") do not work because of
-// <base href="../"> pointing to the root folder. This function *fixes*
-// that in a very inelegant way
-fn fix_anchor_links(html: String, filename: &str) -> String {
-    let regex = Regex::new(r##"<a([^>]+)href="#([^"]+)"([^>]*)>"##).unwrap();
-    regex.replace_all(&html, |caps: &Captures| {
-        let before = &caps[1];
-        let anchor = &caps[2];
-        let after = &caps[3];
-
-        format!("<a{before}href=\"{filename}#{anchor}\"{after}>",
-            before=before, filename=filename, anchor=anchor, after=after)
-    }).into_owned()
+// The 404 page is written for `mdbook serve`, which sets the live reload, and for the books asking
+// for it with `output.html.not-found`
+fn has_not_found_page(book: &MDBook) -> bool {
+    if book.get_livereload().is_some() {
+        return true;
+    }
+    match book.get_config().get_deserialized::<bool>("output.html.not-found") {
+        Some(Ok(not_found)) => not_found,
+        Some(Err(e)) => {
+            warn!("[*]: Invalid output.html.not-found, no 404.html is written: {}", e);
+            false
+        },
+        None => false,
+    }
 }
 
-
-// The rust book uses annotations for rustdoc to test code snippets, like the following:
-// ```rust,should_panic
-// fn main() {
-//     // Code here
-// }
-// ```
-// This function replaces all commas by spaces in the code block classes
-fn fix_code_blocks(html: String) -> String {
-    let regex = Regex::new(r##"<code([^>]+)class="([^"]+)"([^>]*)>"##).unwrap();
-    regex.replace_all(&html, |caps: &Captures| {
-        let before = &caps[1];
-        let classes = &caps[2].replace(",", " ");
-        let after = &caps[3];
-
-        format!("<code{before}class=\"{classes}\"{after}>", before=before, classes=classes, after=after)
-    }).into_owned()
+// Where the book is on its server, like `/` or `/my-book/`
+fn site_url(book: &MDBook) -> String {
+    match book.get_config().get_deserialized::<String>("output.html.site-url") {
+        Some(Ok(ref url)) if url.ends_with('/') => url.clone(),
+        Some(Ok(url)) => format!("{}/", url),
+        Some(Err(e)) => {
+            warn!("[*]: Invalid output.html.site-url, using /: {}", e);
+            "/".to_owned()
+        },
+        None => "/".to_owned(),
+    }
 }
 
-fn add_playpen_pre(html: String) -> String {
-    let regex = Regex::new(r##"((?s)<code[^>]?class="([^"]+)".*?>(.*?)</code>)"##).unwrap();
-    regex.replace_all(&html, |caps: &Captures| {
-        let text = &caps[1];
-        let classes = &caps[2];
-        let code = &caps[3];
-
-        if classes.contains("language-rust") && !classes.contains("ignore") {
-            // wrap the contents in an external pre block
-
-            if text.contains("fn main") {
-                format!("<pre class=\"playpen\">{}</pre>", text)
-            } else {
-                // we need to inject our own main
-                let (attrs, code) = partition_source(code);
-                format!("<pre class=\"playpen\"><code class=\"{}\"># #![allow(unused_variables)]
-{}#fn main() {{
-{}
-#}}</code></pre>", classes, attrs, code)
-            }
-        } else {
-            // not language-rust, so no-op
-            format!("{}", text)
-        }
-    }).into_owned()
+// The path of the page of a chapter, as used in links
+fn page_path(book: &MDBook, ch: &Chapter) -> String {
+    // NOTE: Removes the extension. May or may not be wanted.
+    let path = if book.page_extension {
+        ch.path.clone()
+    } else {
+        ch.path.with_extension("")
+    };
+    path.to_string_lossy().into_owned()
 }
 
-fn partition_source(s: &str) -> (String, String) {
-    let mut after_header = false;
-    let mut before = String::new();
-    let mut after = String::new();
-
-    for line in s.lines() {
-        let trimline = line.trim();
-        let header = trimline.chars().all(|c| c.is_whitespace()) ||
-            trimline.starts_with("#![");
-        if !header || after_header {
-            after_header = true;
-            after.push_str(line);
-            after.push_str("\n");
-        } else {
-            before.push_str(line);
-            before.push_str("\n");
-        }
+fn make_data(book: &MDBook) -> Result<serde_json::Map<String, serde_json::Value>> {
+    debug!("[fn]: make_data");
+
+    let mut data = serde_json::Map::new();
+    data.insert("language".to_owned(), json!(book.get_config().book.language));
+    data.insert("title".to_owned(), json!(book.get_title()));
+    data.insert("description".to_owned(), json!(book.get_description()));
+    data.insert("favicon".to_owned(), json!("favicon.png"));
+    data.insert("playpen_url".to_owned(), json!(PlaypenConfig::from_book(book).url));
+    if let Some(livereload) = book.get_livereload() {
+        data.insert("livereload".to_owned(), json!(livereload.script()));
     }
 
-    (before, after)
+    // NOTE: Simply use the serde default serialization. Why not?
+    // The chapter contents are left out, the templates only need the structure of the book.
+    let chapters: Vec<_> = book.content.iter().map(|item| item.without_content()).collect();
+    data.insert("chapters".to_owned(), json!(chapters));
+
+    debug!("[*]: JSON constructed");
+    Ok(data)
 }
@@ -1,4 +1,3 @@
 pub mod navigation;
 pub mod toc;
-pub mod playpen;
 pub mod text;
@@ -4,8 +4,16 @@ use std::collections::{VecDeque, BTreeMap};
 use serde_json;
 use handlebars::{Handlebars, RenderError, RenderContext, Helper, Renderable};
 
-use book::bookitem::{Chapter, BookItem};
-
+use book::bookitem::{Chapter, BookItem, BookItems};
+
+// The chapters are nested like in the table of contents, the navigation follows the reading order
+fn reading_order(items: &[BookItem]) -> Vec<&BookItem> {
+    BookItems {
+        items: items,
+        current_index: 0,
+        stack: Vec::new(),
+    }.collect()
+}
 
 // Handlebars helper for navigation
 pub fn previous(_h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
@@ -31,10 +39,10 @@ pub fn previous(_h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(
     };
     let mut previous: Option<Chapter> = None;
 
-    for item in decoded {
-        match item {
-            BookItem::Chapter(ch) | BookItem::Affix(ch) => {
-                if ch.path != Path::new("") {
+    for item in reading_order(&decoded) {
+        match *item {
+            BookItem::Chapter(ref ch) | BookItem::Affix(ref ch) => {
+                if !ch.is_draft() {
                     if ch.path == Path::new(&current) {
                         match previous {
                             Some(ref mut prev) => {
@@ -62,7 +70,7 @@ pub fn previous(_h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(
                         }
                         break;
                     } else {
-                        previous = Some(ch);
+                        previous = Some(ch.clone());
                     }
                 }
             },
@@ -94,10 +102,10 @@ pub fn next(_h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), R
     };
     let mut next: Option<Chapter> = None;
 
-    for item in decoded.into_iter().rev() {
-        match item {
-            BookItem::Chapter(ch) | BookItem::Affix(ch) => {
-                if ch.path != Path::new("") {
+    for item in reading_order(&decoded).into_iter().rev() {
+        match *item {
+            BookItem::Chapter(ref ch) | BookItem::Affix(ref ch) => {
+                if !ch.is_draft() {
                     if ch.path == Path::new(&current) {
                         match next {
                             Some(ref mut prev) => {
@@ -125,7 +133,7 @@ pub fn next(_h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), R
                         }
                         break;
                     } else {
-                        next = Some(ch);
+                        next = Some(ch.clone());
                     }
                 }
             },
@@ -4,7 +4,7 @@ use std::collections::{VecDeque};
 use serde_json;
 use handlebars::{Handlebars, HelperDef, RenderError, RenderContext, Helper};
 // use pulldown_cmark::{Parser, html, Event, Tag};
-use book::BookItem;
+use book::bookitem::{BookItem, Chapter};
 
 // Handlebars helper to construct TOC
 #[derive(Clone, Copy)]
@@ -39,6 +39,10 @@ impl HelperDef for RenderToc {
 fn item_to_li(item: BookItem, current: &Path, rc: &mut RenderContext) -> Result<(), RenderError> {
     match item {
         BookItem::Spacer => write!(rc.writer, "<li class=\"spacer\"></li>")?,
+        // Drafts have no page to link to
+        BookItem::Affix(ref ch) if ch.is_draft() => {
+            write!(rc.writer, "<li class=\"affix\"><span class=\"draft\">{}</span></li>", ch.name)?;
+        },
         BookItem::Affix(ch) => {
             write!(rc.writer, "<li class=\"affix\"><a href=\"/{}\"", ch.path.to_str().unwrap())?;
             if ch.path == current {
@@ -47,12 +51,20 @@ fn item_to_li(item: BookItem, current: &Path, rc: &mut RenderContext) -> Result<
             write!(rc.writer, ">{}</a></li>", ch.name)?;
         },
         BookItem::Chapter(ch) => {
-            let slug = ch.path.file_stem().unwrap().to_str().unwrap();
-            write!(rc.writer, "<li class=\"item\"><a name=\"{}\" href=\"/{}\"", slug, ch.path.to_str().unwrap())?;
-            if ch.path == current {
-                write!(rc.writer, " class=\"active\"")?;
+            if ch.is_draft() {
+                write!(rc.writer, "<li class=\"item\"><span class=\"draft\">")?;
+                write_numbered_name(&ch, rc)?;
+                write!(rc.writer, "</span>")?;
+            } else {
+                let slug = ch.path.file_stem().unwrap().to_str().unwrap();
+                write!(rc.writer, "<li class=\"item\"><a name=\"{}\" href=\"/{}\"", slug, ch.path.to_str().unwrap())?;
+                if ch.path == current {
+                    write!(rc.writer, " class=\"active\"")?;
+                }
+                write!(rc.writer, ">")?;
+                write_numbered_name(&ch, rc)?;
+                write!(rc.writer, "</a>")?;
             }
-            write!(rc.writer, ">{}</a>", ch.name)?;
             if !ch.sub_items.is_empty() {
                 write!(rc.writer, "<ol class=\"section\">")?;
                 for sub in ch.sub_items {
@@ -65,3 +77,11 @@ fn item_to_li(item: BookItem, current: &Path, rc: &mut RenderContext) -> Result<
     }
     Ok(())
 }
+
+fn write_numbered_name(ch: &Chapter, rc: &mut RenderContext) -> Result<(), RenderError> {
+    if let Some(ref number) = ch.number {
+        write!(rc.writer, "<strong>{}</strong> ", number)?;
+    }
+    write!(rc.writer, "{}", ch.name)?;
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, Event, Tag};
+
+use utils;
+
+/// Renders the markdown of chapters to the HTML of a page, `filename`.
+///
+/// The transforms the pages need are done on the markdown events, so they only ever touch the
+/// content of the chapters and not the theme around it:
+///
+/// - headings get an id and a link to themselves
+/// - links to an anchor of the page point to `filename`, the `<base href>` of the theme would
+///   otherwise send them to the root of the book
+/// - the commas of code block annotations (` ```rust,should_panic `) become separate classes
+/// - runnable rust code blocks are wrapped in a playpen, with a `main` function when they
+///   don't have one, unless playpens are turned off with `set_playpens(false)`
+///
+/// The ids of the headings are unique across everything rendered by the same `ChapterRenderer`,
+/// the print page renders all the chapters with one.
+pub struct ChapterRenderer {
+    filename: String,
+    ids: HashMap<String, usize>,
+    playpens: bool,
+}
+
+impl ChapterRenderer {
+    pub fn new<S: Into<String>>(filename: S) -> Self {
+        ChapterRenderer {
+            filename: filename.into(),
+            ids: HashMap::new(),
+            playpens: true,
+        }
+    }
+
+    /// Whether rust code blocks become playpens. Outputs that can't run code, like the EPUB,
+    /// render them as plain code blocks.
+    pub fn set_playpens(mut self, playpens: bool) -> Self {
+        self.playpens = playpens;
+        self
+    }
+
+    pub fn render(&mut self, text: &str) -> String {
+        let mut events = Vec::new();
+        let mut header = None;
+        let mut code_block = None;
+
+        for event in utils::markdown_parser(text) {
+            let event = match event {
+                // Code blocks are rendered at once, when all of their text is known
+                Event::Start(Tag::CodeBlock(info)) => {
+                    code_block = Some((info, String::new()));
+                    continue;
+                },
+                Event::End(Tag::CodeBlock(_)) => {
+                    match code_block.take() {
+                        Some((info, code)) => Event::Html(render_code_block(&info, &code, self.playpens).into()),
+                        None => continue,
+                    }
+                },
+                Event::Text(text) => {
+                    match code_block {
+                        Some((_, ref mut code)) => {
+                            code.push_str(&text);
+                            continue;
+                        },
+                        None => Event::Text(text),
+                    }
+                },
+                Event::Start(Tag::Link(ref dest, ref title)) if dest.starts_with('#') => {
+                    Event::Start(Tag::Link(format!("{}{}", self.filename, dest).into(), title.clone()))
+                },
+                event => event,
+            };
+
+            // The id of a heading depends on its text, so its events are held until its end
+            match event {
+                Event::Start(Tag::Header(level)) => header = Some((level, Vec::new())),
+                Event::End(Tag::Header(level)) => {
+                    if let Some((_, inner)) = header.take() {
+                        let id = self.unique_id(&header_text(&inner));
+                        let open = format!("<a class=\"header\" href=\"{filename}#{id}\" id=\"{id}\"><h{level}>",
+                                           filename = self.filename,
+                                           id = id,
+                                           level = level);
+                        events.push(Event::Html(open.into()));
+                        events.extend(inner);
+                        events.push(Event::Html(format!("</h{}></a>\n", level).into()));
+                    }
+                },
+                event => {
+                    match header {
+                        Some((_, ref mut inner)) => inner.push(event),
+                        None => events.push(event),
+                    }
+                },
+            }
+        }
+
+        let mut s = String::with_capacity(text.len() * 3 / 2);
+        html::push_html(&mut s, events.into_iter());
+        s
+    }
+
+    // Headings with the same text get a `-1`, `-2`, ... suffix
+    fn unique_id(&mut self, text: &str) -> String {
+        let id = normalize_id(text);
+        let count = self.ids.entry(id.clone()).or_insert(0);
+        *count += 1;
+
+        if *count > 1 {
+            format!("{}-{}", id, *count - 1)
+        } else {
+            id
+        }
+    }
+}
+
+fn header_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        if let Event::Text(ref t) = *event {
+            text.push_str(t);
+        }
+    }
+    text
+}
+
+fn normalize_id(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| if c.is_alphanumeric() || c == '-' || c == '_' {
+            if c.is_ascii() {
+                Some(c.to_ascii_lowercase())
+            } else {
+                Some(c)
+            }
+        } else if c.is_whitespace() && c.is_ascii() {
+            Some('-')
+        } else {
+            None
+        })
+        .collect()
+}
+
+// The rust book uses annotations for rustdoc to test code snippets, like the following:
+// ```rust,should_panic
+// fn main() {
+//     // Code here
+// }
+// ```
+// Every annotation becomes a class of the code block, the first one being the language
+fn render_code_block(info: &str, code: &str, playpens: bool) -> String {
+    let annotations: Vec<&str> = info.split(|c: char| c == ',' || c.is_whitespace())
+                                     .filter(|a| !a.is_empty())
+                                     .collect();
+    let classes = annotations.iter()
+                             .enumerate()
+                             .map(|(i, a)| if i == 0 { format!("language-{}", a) } else { a.to_string() })
+                             .collect::<Vec<_>>()
+                             .join(" ");
+    let has = |annotation: &str| annotations.iter().any(|&a| a == annotation);
+
+    if playpens && annotations.first() == Some(&"rust") && !has("ignore") {
+        if code.contains("fn main") {
+            format!("<pre class=\"playpen\"><code class=\"{}\">{}</code></pre>\n",
+                    escape(&classes),
+                    escape(code))
+        } else {
+            // we need to inject our own main
+            let (attrs, code) = partition_source(code);
+            format!("<pre class=\"playpen\"><code class=\"{}\"># #![allow(unused_variables)]
+{}#fn main() {{
+{}
+#}}</code></pre>\n",
+                    escape(&classes),
+                    escape(&attrs),
+                    escape(&code))
+        }
+    } else if classes.is_empty() {
+        format!("<pre><code>{}</code></pre>\n", escape(code))
+    } else {
+        format!("<pre><code class=\"{}\">{}</code></pre>\n", escape(&classes), escape(code))
+    }
+}
+
+fn partition_source(s: &str) -> (String, String) {
+    let mut after_header = false;
+    let mut before = String::new();
+    let mut after = String::new();
+
+    for line in s.lines() {
+        let trimline = line.trim();
+        let header = trimline.chars().all(|c| c.is_whitespace()) ||
+            trimline.starts_with("#![");
+        if !header || after_header {
+            after_header = true;
+            after.push_str(line);
+            after.push_str("\n");
+        } else {
+            before.push_str(line);
+            before.push_str("\n");
+        }
+    }
+
+    (before, after)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::ChapterRenderer;
+
+    #[test]
+    fn it_links_the_headings() {
+        let mut renderer = ChapterRenderer::new("intro.html");
+        let html = renderer.render("# Hello `World`\n\n## Hello World\n\n```\n<h1>Not a heading</h1>\n```\n");
+
+        assert!(html.contains("<a class=\"header\" href=\"intro.html#hello-world\" id=\"hello-world\">\
+                               <h1>Hello <code>World</code></h1></a>"));
+        assert!(html.contains("<a class=\"header\" href=\"intro.html#hello-world-1\" id=\"hello-world-1\">\
+                               <h2>Hello World</h2></a>"));
+        assert!(html.contains("<pre><code>&lt;h1&gt;Not a heading&lt;/h1&gt;\n</code></pre>"));
+    }
+
+    #[test]
+    fn it_keeps_the_ids_unique_across_chapters() {
+        let mut renderer = ChapterRenderer::new("print.html");
+        renderer.render("# Introduction\n");
+        let html = renderer.render("# Introduction\n");
+
+        assert!(html.contains("id=\"introduction-1\""));
+    }
+
+    #[test]
+    fn it_points_anchors_to_the_page() {
+        let mut renderer = ChapterRenderer::new("format/config.html");
+        let html = renderer.render("See [below](#dest), [the cli](../cli/build.html#dest) and `href=\"#x\"`.");
+
+        assert!(html.contains("<a href=\"format/config.html#dest\">below</a>"));
+        assert!(html.contains("<a href=\"../cli/build.html#dest\">the cli</a>"));
+        assert!(html.contains("<code>href=&quot;#x&quot;</code>"));
+    }
+
+    #[test]
+    fn it_renders_code_blocks() {
+        let mut renderer = ChapterRenderer::new("rust.html");
+
+        let html = renderer.render("```rust,should_panic\n#![allow(dead_code)]\nlet x = 5;\n```\n");
+        assert_eq!(html,
+                   "<pre class=\"playpen\"><code class=\"language-rust should_panic\"># #![allow(unused_variables)]
+#![allow(dead_code)]
+#fn main() {
+let x = 5;
+
+#}</code></pre>\n");
+
+        let html = renderer.render("```rust,editable\nlet x = 5;\n```\n");
+        assert_eq!(html,
+                   "<pre class=\"playpen\"><code class=\"language-rust editable\"># #![allow(unused_variables)]
+#fn main() {
+let x = 5;
+
+#}</code></pre>\n");
+
+        let html = renderer.render("```rust,editable\nfn main() {}\n```\n");
+        assert_eq!(html, "<pre class=\"playpen\"><code class=\"language-rust editable\">fn main() {}\n</code></pre>\n");
+
+        let html = renderer.render("```rust,ignore\nlet x = 5;\n```\n");
+        assert_eq!(html, "<pre><code class=\"language-rust ignore\">let x = 5;\n</code></pre>\n");
+
+        let html = renderer.render("```toml\n[book]\n```\n");
+        assert_eq!(html, "<pre><code class=\"language-toml\">[book]\n</code></pre>\n");
+
+        let mut renderer = ChapterRenderer::new("rust.xhtml").set_playpens(false);
+        let html = renderer.render("```rust\nlet x = 5;\n```\n");
+        assert_eq!(html, "<pre><code class=\"language-rust\">let x = 5;\n</code></pre>\n");
+    }
+}
@@ -1,4 +1,8 @@
 pub use self::hbs_renderer::HtmlHandlebars;
+pub use self::markdown::ChapterRenderer;
 
 mod hbs_renderer;
 mod helpers;
+mod markdown;
+mod playpen;
+mod search;
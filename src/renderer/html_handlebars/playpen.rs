@@ -0,0 +1,56 @@
+use book::MDBook;
+
+/// The playground used by default to run the code blocks
+pub const DEFAULT_URL: &'static str = "https://play.rust-lang.org/evaluate.json";
+
+/// Configuration of the runnable code blocks, read from the `[output.html.playpen]` table
+///
+/// ```toml
+/// [output.html.playpen]
+/// url = "http://localhost:3000/evaluate.json"   # Where the Run buttons send the code
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PlaypenConfig {
+    pub url: String,
+}
+
+impl Default for PlaypenConfig {
+    fn default() -> Self {
+        PlaypenConfig { url: DEFAULT_URL.to_owned() }
+    }
+}
+
+impl PlaypenConfig {
+    pub fn from_book(book: &MDBook) -> Self {
+        match book.get_config().get_deserialized("output.html.playpen") {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                warn!("[*]: Invalid [output.html.playpen] table, using the defaults: {}", e);
+                PlaypenConfig::default()
+            },
+            None => PlaypenConfig::default(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{PlaypenConfig, DEFAULT_URL};
+    use book::MDBook;
+    use utils::testing::{temp_dir, write_files};
+
+    #[test]
+    fn it_reads_the_playpen_url_from_the_config() {
+        let tmp = temp_dir(&[]);
+
+        let book = MDBook::new(tmp.path()).read_config().unwrap();
+        assert_eq!(PlaypenConfig::from_book(&book).url, DEFAULT_URL);
+
+        write_files(tmp.path(),
+                    &[("book.toml", "[output.html.playpen]\nurl = \"http://localhost:3000/evaluate.json\"\n")]);
+        let book = MDBook::new(tmp.path()).read_config().unwrap();
+        assert_eq!(PlaypenConfig::from_book(&book).url, "http://localhost:3000/evaluate.json");
+    }
+}
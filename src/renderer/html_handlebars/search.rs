@@ -0,0 +1,196 @@
+use book::MDBook;
+use regex::{Regex, Captures};
+
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+
+use serde_json;
+
+/// Configuration of the search, read from the `[output.html.search]` table
+///
+/// ```toml
+/// [output.html.search]
+/// enable = true            # Generate the search index and show the search box
+/// limit-results = 30       # Maximum number of results shown
+/// boost-title = 2          # Weight of the words in the section titles
+/// boost-paragraph = 1      # Weight of the words in the section bodies
+/// exclude = ["misc/contributors.md"]   # Chapters left out of the index
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SearchConfig {
+    pub enable: bool,
+    pub limit_results: u64,
+    pub boost_title: u64,
+    pub boost_paragraph: u64,
+    pub exclude: Vec<PathBuf>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            enable: true,
+            limit_results: 30,
+            boost_title: 2,
+            boost_paragraph: 1,
+            exclude: vec![],
+        }
+    }
+}
+
+impl SearchConfig {
+    pub fn from_book(book: &MDBook) -> Self {
+        match book.get_config().get_deserialized("output.html.search") {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                warn!("[*]: Invalid [output.html.search] table, using the defaults: {}", e);
+                SearchConfig::default()
+            },
+            None => SearchConfig::default(),
+        }
+    }
+
+    /// Whether the chapter at `path` (relative to the source directory) is left out of the index
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let path = path.with_extension("");
+        self.exclude.iter().any(|excluded| excluded.with_extension("") == path)
+    }
+}
+
+/// A section of a chapter, as shown in the search results
+#[derive(Serialize)]
+struct Document {
+    title: String,
+    breadcrumbs: String,
+    url: String,
+    teaser: String,
+}
+
+/// The search index of the book, built chapter by chapter while rendering. Every heading of a
+/// chapter starts a new document, so that results point to the right section.
+pub struct SearchIndex {
+    config: SearchConfig,
+    docs: Vec<Document>,
+    // word -> document -> score
+    index: BTreeMap<String, BTreeMap<usize, u64>>,
+}
+
+impl SearchIndex {
+    pub fn new(config: SearchConfig) -> Self {
+        SearchIndex {
+            config: config,
+            docs: vec![],
+            index: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the sections of a rendered chapter to the index. `html` is the content of the chapter
+    /// rendered by `ChapterRenderer`, whose headings link to themselves, and `url` the path of the
+    /// chapter's page.
+    pub fn add_chapter(&mut self, chapter: &str, url: &str, html: &str) {
+        lazy_static! {
+            static ref HEADING: Regex =
+                Regex::new(r#"<a class="header" href="[^"]*" id="([^"]*)"><h\d>(.*?)</h\d></a>"#).unwrap();
+        }
+
+        // The text before the first heading belongs to the chapter itself
+        let mut title = chapter.to_owned();
+        let mut section_url = url.to_owned();
+        let mut start = 0;
+
+        for caps in HEADING.captures_iter(html) {
+            let heading = caps.get(0).unwrap();
+            self.add_document(chapter, &title, &section_url, &html[start..heading.start()]);
+
+            title = html_to_text(&caps[2]);
+            section_url = format!("{}#{}", url, &caps[1]);
+            start = heading.end();
+        }
+
+        self.add_document(chapter, &title, &section_url, &html[start..]);
+    }
+
+    fn add_document(&mut self, chapter: &str, title: &str, url: &str, body: &str) {
+        let body = html_to_text(body);
+        if title.is_empty() && body.trim().is_empty() {
+            return;
+        }
+
+        let id = self.docs.len();
+        let breadcrumbs = if title == chapter {
+            chapter.to_owned()
+        } else {
+            format!("{} » {}", chapter, title)
+        };
+
+        for word in tokenize(title) {
+            *self.index.entry(word).or_insert_with(BTreeMap::new).entry(id).or_insert(0) += self.config.boost_title;
+        }
+        for word in tokenize(&body) {
+            *self.index.entry(word).or_insert_with(BTreeMap::new).entry(id).or_insert(0) +=
+                self.config.boost_paragraph;
+        }
+
+        self.docs.push(Document {
+            title: title.to_owned(),
+            breadcrumbs: breadcrumbs,
+            url: url.to_owned(),
+            teaser: teaser(&body),
+        });
+    }
+
+    /// The index as JSON, in the format expected by `book.js`
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let index: BTreeMap<&String, Vec<(usize, u64)>> = self.index
+                                                              .iter()
+                                                              .map(|(word, docs)| {
+                                                                  (word, docs.iter().map(|(&d, &s)| (d, s)).collect())
+                                                              })
+                                                              .collect();
+
+        serde_json::to_string(&json!({
+            "config": {
+                "limit_results": self.config.limit_results,
+            },
+            "docs": self.docs,
+            "index": index,
+        }))
+    }
+}
+
+// Strips the tags and decodes the common entities
+fn html_to_text(html: &str) -> String {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+        static ref ENTITY: Regex = Regex::new(r"&(lt|gt|amp|quot|#39|nbsp);").unwrap();
+    }
+
+    let text = TAG.replace_all(html, " ");
+    ENTITY.replace_all(&text, |caps: &Captures| {
+        match &caps[1] {
+            "lt" => "<",
+            "gt" => ">",
+            "amp" => "&",
+            "quot" => "\"",
+            "#39" => "'",
+            _ => " ",
+        }.to_owned()
+    }).into_owned()
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// The beginning of the section, shown under the search results
+fn teaser(body: &str) -> String {
+    let words: Vec<&str> = body.split_whitespace().collect();
+    let mut teaser = words.iter().take(30).cloned().collect::<Vec<_>>().join(" ");
+    if words.len() > 30 {
+        teaser.push_str(" ...");
+    }
+    teaser
+}
@@ -0,0 +1,486 @@
+use renderer::Renderer;
+use book::MDBook;
+use book::bookitem::{BookItem, Chapter};
+use errors::{Error, Result};
+use utils;
+
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::mem;
+use std::collections::{BTreeSet, HashMap};
+
+use pulldown_cmark::{Event, Tag};
+use pulldown_cmark::Alignment;
+
+const PREAMBLE: &'static str = r#"\documentclass[a4paper]{report}
+
+\usepackage[utf8]{inputenc}
+\usepackage[T1]{fontenc}
+\usepackage{lmodern}
+\usepackage{graphicx}
+\usepackage{listings}
+\usepackage{xcolor}
+\usepackage[hidelinks]{hyperref}
+
+\lstdefinelanguage{Rust}{
+    keywords={as, break, const, continue, crate, else, enum, extern, false, fn, for, if, impl, in, let,
+        loop, match, mod, move, mut, pub, ref, return, self, Self, static, struct, super, trait, true,
+        type, unsafe, use, where, while},
+    sensitive=true,
+    comment=[l]{//},
+    morecomment=[s]{/*}{*/},
+    morestring=[b]",
+}
+
+\lstset{
+    basicstyle=\ttfamily\small,
+    keywordstyle=\bfseries,
+    commentstyle=\color{gray},
+    breaklines=true,
+    columns=fullflexible,
+    frame=single,
+    upquote=true,
+}
+
+"#;
+
+/// Renders the book as a LaTeX document, `book.tex`, in the output directory.
+///
+/// The chapters become `\chapter`s, `\section`s, ... following their nesting in `SUMMARY.md`.
+/// Code blocks become `lstlisting` environments with the language of the fence, and tables,
+/// footnotes and images are supported. Images are copied next to `book.tex` so the document
+/// compiles with a local TeX installation, e.g. `pdflatex book.tex`.
+#[derive(Default)]
+pub struct LatexRenderer;
+
+impl LatexRenderer {
+    pub fn new() -> Self {
+        LatexRenderer
+    }
+}
+
+impl Renderer for LatexRenderer {
+    fn name(&self) -> &str {
+        "latex"
+    }
+
+    fn render(&self, book: &MDBook) -> Result<()> {
+        debug!("[fn]: LatexRenderer::render");
+
+        let mut images = BTreeSet::new();
+
+        let mut tex = String::from(PREAMBLE);
+        tex.push_str(&format!("\\title{{{}}}\n", escape(book.get_title())));
+        tex.push_str(&format!("\\author{{{}}}\n", escape(book.get_author())));
+        tex.push_str("\\date{}\n\n");
+        tex.push_str("\\begin{document}\n\n\\maketitle\n\\tableofcontents\n\n");
+
+        render_items(book, &book.content, 0, &mut images, &mut tex);
+
+        tex.push_str("\\end{document}\n");
+
+        info!("[*] Creating book.tex ✓");
+        book.write_file("book.tex", tex.as_bytes())?;
+
+        for image in &images {
+            let dest = book.get_dest().join(image);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+            }
+
+            let source = book.get_src().join(image);
+            fs::copy(&source, &dest).map_err(|e| Error::io(source, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn render_items(book: &MDBook, items: &[BookItem], depth: usize, images: &mut BTreeSet<PathBuf>, tex: &mut String) {
+    for item in items {
+        match *item {
+            BookItem::Chapter(ref ch) => {
+                tex.push_str(&format!("\\{}{{{}}}\n\n", sectioning(depth), escape(&ch.name)));
+                render_chapter(book, ch, depth, images, tex);
+                render_items(book, &ch.sub_items, depth + 1, images, tex);
+            },
+            BookItem::Affix(ref ch) => {
+                // Affixes are not numbered, but still listed in the table of contents
+                tex.push_str(&format!("\\{}*{{{}}}\n", sectioning(depth), escape(&ch.name)));
+                tex.push_str(&format!("\\addcontentsline{{toc}}{{{}}}{{{}}}\n\n",
+                                      sectioning(depth),
+                                      escape(&ch.name)));
+                render_chapter(book, ch, depth, images, tex);
+            },
+            BookItem::Spacer => {},
+        }
+    }
+}
+
+fn render_chapter(book: &MDBook, ch: &Chapter, depth: usize, images: &mut BTreeSet<PathBuf>, tex: &mut String) {
+    if ch.is_draft() {
+        return;
+    }
+
+    // Images are relative to the chapter's source file
+    let source = ch.source_path(book.get_src());
+    let dir = source.strip_prefix(book.get_src())
+                    .ok()
+                    .and_then(|p| p.parent())
+                    .unwrap_or_else(|| Path::new(""))
+                    .to_owned();
+
+    // The same parser as the other outputs, so the chapters read the same in the document
+    let mut writer = LatexWriter::new(depth, &dir, book.get_src(), images);
+    for event in utils::markdown_parser(&ch.content) {
+        writer.event(event);
+    }
+
+    tex.push_str(&writer.finish());
+    tex.push_str("\n");
+}
+
+// A part of the LaTeX of a chapter. Footnotes can be defined after they are used, their text is
+// only known at the end of the chapter.
+enum Part {
+    Text(String),
+    Footnote(String),
+}
+
+// Turns the markdown events of one chapter into LaTeX
+struct LatexWriter<'a> {
+    out: String,
+    parts: Vec<Part>,
+    depth: usize,
+    dir: &'a Path,
+    src: &'a Path,
+    images: &'a mut BTreeSet<PathBuf>,
+
+    // The title of the chapter is already given by `SUMMARY.md`
+    seen_heading: bool,
+    skip_text: bool,
+    code_block: Option<(Option<&'static str>, String)>,
+    table_cell: usize,
+    // The footnote being defined, with the output around it
+    footnote: Option<(String, String, Vec<Part>)>,
+    footnotes: HashMap<String, String>,
+}
+
+impl<'a> LatexWriter<'a> {
+    fn new(depth: usize, dir: &'a Path, src: &'a Path, images: &'a mut BTreeSet<PathBuf>) -> Self {
+        LatexWriter {
+            out: String::new(),
+            parts: vec![],
+            depth: depth,
+            dir: dir,
+            src: src,
+            images: images,
+            seen_heading: false,
+            skip_text: false,
+            code_block: None,
+            table_cell: 0,
+            footnote: None,
+            footnotes: HashMap::new(),
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if self.skip_text {
+                    return;
+                }
+                match self.code_block {
+                    Some((_, ref mut code)) => code.push_str(&text),
+                    None => self.out.push_str(&escape(&text)),
+                }
+            },
+            Event::FootnoteReference(name) => {
+                let text = mem::replace(&mut self.out, String::new());
+                self.parts.push(Part::Text(text));
+                self.parts.push(Part::Footnote(name.into_owned()));
+            },
+            Event::SoftBreak => self.out.push('\n'),
+            Event::HardBreak => self.out.push_str("\\newline\n"),
+            Event::Html(html) |
+            Event::InlineHtml(html) => debug!("[*]: Skipping HTML in LaTeX output: {}", html),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {},
+            Tag::Rule => self.out.push_str("\\noindent\\rule{\\linewidth}{0.4pt}\n\n"),
+            Tag::Header(level) => {
+                if level == 1 && !self.seen_heading {
+                    self.skip_text = true;
+                } else {
+                    let depth = self.depth + level as usize - 1;
+                    self.out.push_str(&format!("\\{}*{{", sectioning(depth)));
+                }
+                self.seen_heading = true;
+            },
+            Tag::BlockQuote => self.out.push_str("\\begin{quote}\n"),
+            Tag::CodeBlock(info) => {
+                let language = info.split(|c| c == ',' || c == ' ').next().and_then(listings_language);
+                self.code_block = Some((language, String::new()));
+            },
+            Tag::List(Some(start)) => {
+                self.out.push_str("\\begin{enumerate}\n");
+                if start != 1 {
+                    self.out.push_str(&format!("\\setcounter{{enumi}}{{{}}}\n", start - 1));
+                }
+            },
+            Tag::List(None) => self.out.push_str("\\begin{itemize}\n"),
+            Tag::Item => self.out.push_str("\\item "),
+            Tag::FootnoteDefinition(name) => {
+                let out = mem::replace(&mut self.out, String::new());
+                let parts = mem::replace(&mut self.parts, vec![]);
+                self.footnote = Some((name.into_owned(), out, parts));
+            },
+            Tag::Table(alignments) => {
+                let columns: String = alignments.iter()
+                                                .map(|a| match *a {
+                                                    Alignment::Center => "c|",
+                                                    Alignment::Right => "r|",
+                                                    _ => "l|",
+                                                })
+                                                .collect();
+                self.out.push_str(&format!("\\begin{{center}}\n\\begin{{tabular}}{{|{}}}\n\\hline\n", columns));
+            },
+            Tag::TableHead |
+            Tag::TableRow => self.table_cell = 0,
+            Tag::TableCell => {
+                if self.table_cell > 0 {
+                    self.out.push_str(" & ");
+                }
+                self.table_cell += 1;
+            },
+            Tag::Emphasis => self.out.push_str("\\emph{"),
+            Tag::Strong => self.out.push_str("\\textbf{"),
+            Tag::Code => self.out.push_str("\\texttt{"),
+            Tag::Link(dest, _) => {
+                if is_external(&dest) {
+                    self.out.push_str(&format!("\\href{{{}}}{{", escape_url(&dest)));
+                } else {
+                    // Links inside the book have no target in the document, keep the text
+                    self.out.push_str("{");
+                }
+            },
+            Tag::Image(dest, _) => {
+                self.skip_text = true;
+                self.image(&dest);
+            },
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.out.push_str("\n\n"),
+            Tag::Rule => {},
+            Tag::Header(_) => {
+                if self.skip_text {
+                    self.skip_text = false;
+                } else {
+                    self.out.push_str("}\n\n");
+                }
+            },
+            Tag::BlockQuote => self.out.push_str("\\end{quote}\n\n"),
+            Tag::CodeBlock(_) => {
+                if let Some((language, code)) = self.code_block.take() {
+                    match language {
+                        Some(language) => self.out.push_str(&format!("\\begin{{lstlisting}}[language={}]\n", language)),
+                        None => self.out.push_str("\\begin{lstlisting}\n"),
+                    }
+                    for line in code.lines() {
+                        // Lines hidden in the html output are hidden here too
+                        if language == Some("Rust") && (line == "#" || line.starts_with("# ")) {
+                            continue;
+                        }
+                        self.out.push_str(line);
+                        self.out.push('\n');
+                    }
+                    self.out.push_str("\\end{lstlisting}\n\n");
+                }
+            },
+            Tag::List(Some(_)) => self.out.push_str("\\end{enumerate}\n\n"),
+            Tag::List(None) => self.out.push_str("\\end{itemize}\n\n"),
+            Tag::Item => self.out.push('\n'),
+            Tag::FootnoteDefinition(_) => {
+                if let Some((name, out, parts)) = self.footnote.take() {
+                    // Footnotes referenced from a footnote are dropped, LaTeX can't nest them
+                    let text = mem::replace(&mut self.out, out);
+                    self.parts = parts;
+                    self.footnotes.insert(name, text.trim().replace("\n\n", " "));
+                }
+            },
+            Tag::Table(_) => self.out.push_str("\\end{tabular}\n\\end{center}\n\n"),
+            Tag::TableHead |
+            Tag::TableRow => self.out.push_str(" \\\\\n\\hline\n"),
+            Tag::TableCell => {},
+            Tag::Emphasis |
+            Tag::Strong |
+            Tag::Code |
+            Tag::Link(..) => self.out.push_str("}"),
+            Tag::Image(..) => self.skip_text = false,
+        }
+    }
+
+    fn image(&mut self, dest: &str) {
+        if is_external(dest) {
+            warn!("[*]: Remote images are not supported in the LaTeX output: {}", dest);
+            return;
+        }
+
+        let path = utils::fs::normalize_path(&self.dir.join(dest));
+        let supported = path.extension()
+                            .and_then(|e| e.to_str())
+                            .map(|e| ["png", "jpg", "jpeg", "pdf"].contains(&e.to_lowercase().as_str()))
+                            .unwrap_or(false);
+
+        if !supported {
+            warn!("[*]: Image format not supported in the LaTeX output: {:?}", path);
+        } else if !self.src.join(&path).is_file() {
+            warn!("[*]: Image {:?} does not exist", path);
+        } else {
+            let graphics_path = path.to_string_lossy().replace('\\', "/");
+            self.out.push_str(&format!("\\includegraphics[width=\\linewidth,keepaspectratio]{{{}}}", graphics_path));
+            self.images.insert(path);
+        }
+    }
+
+    // Footnotes can be defined after they are used, they are put in place at the end
+    fn finish(mut self) -> String {
+        let last = mem::replace(&mut self.out, String::new());
+        self.parts.push(Part::Text(last));
+
+        let mut out = String::new();
+        for part in &self.parts {
+            match *part {
+                Part::Text(ref text) => out.push_str(text),
+                Part::Footnote(ref name) => {
+                    match self.footnotes.get(name) {
+                        Some(text) => out.push_str(&format!("\\footnote{{{}}}", text)),
+                        None => warn!("[*]: Footnote '{}' is not defined", name),
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+// Sectioning commands for the given nesting depth
+fn sectioning(depth: usize) -> &'static str {
+    match depth {
+        0 => "chapter",
+        1 => "section",
+        2 => "subsection",
+        3 => "subsubsection",
+        4 => "paragraph",
+        _ => "subparagraph",
+    }
+}
+
+// Languages known to the listings package, `None` for the others to avoid compilation errors
+fn listings_language(fence: &str) -> Option<&'static str> {
+    match fence.to_lowercase().as_str() {
+        "rust" => Some("Rust"),
+        "c" => Some("C"),
+        "cpp" | "c++" => Some("C++"),
+        "java" => Some("Java"),
+        "python" | "py" => Some("Python"),
+        "bash" | "sh" | "shell" => Some("bash"),
+        "html" => Some("HTML"),
+        "xml" => Some("XML"),
+        "sql" => Some("SQL"),
+        "ruby" => Some("Ruby"),
+        "haskell" => Some("Haskell"),
+        _ => None,
+    }
+}
+
+fn is_external(dest: &str) -> bool {
+    dest.contains("://") || dest.starts_with("mailto:")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\")
+       .replace('#', "\\#")
+       .replace('%', "\\%")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{LatexWriter, escape};
+    use utils;
+    use std::collections::BTreeSet;
+    use std::path::Path;
+
+    fn render(markdown: &str) -> String {
+        let mut images = BTreeSet::new();
+        let mut writer = LatexWriter::new(0, Path::new(""), Path::new(""), &mut images);
+        for event in utils::markdown_parser(markdown) {
+            writer.event(event);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn it_renders_headings_as_unnumbered_sections() {
+        // The first heading is the title of the chapter, already given by SUMMARY.md
+        assert_eq!(render("# Title\n\nText\n\n## Section\n\n### Subsection\n"),
+                   "Text\n\n\\section*{Section}\n\n\\subsection*{Subsection}\n\n");
+    }
+
+    #[test]
+    fn it_escapes_special_characters() {
+        assert_eq!(escape(r"# $ % & _ { } ~ ^ \"),
+                   r"\# \$ \% \& \_ \{ \} \textasciitilde{} \textasciicircum{} \textbackslash{}");
+        assert_eq!(render("50% of $5 & more\n"), "50\\% of \\$5 \\& more\n\n");
+    }
+
+    #[test]
+    fn it_renders_code_blocks_as_listings() {
+        assert_eq!(render("```rust\n# use std::fmt;\nfn main() {}\n```\n"),
+                   "\\begin{lstlisting}[language=Rust]\nfn main() {}\n\\end{lstlisting}\n\n");
+        assert_eq!(render("```unknown\n$ run\n```\n"),
+                   "\\begin{lstlisting}\n$ run\n\\end{lstlisting}\n\n");
+    }
+
+    #[test]
+    fn it_renders_tables() {
+        let tex = render("| a | b |\n|---|--:|\n| 1 | 2 |\n");
+        assert!(tex.starts_with("\\begin{center}\n\\begin{tabular}{|l|r|}\n\\hline\n"), tex);
+        assert!(tex.contains(" & "), tex);
+        assert!(tex.ends_with(" \\\\\n\\hline\n\\end{tabular}\n\\end{center}\n\n"), tex);
+    }
+
+    #[test]
+    fn it_puts_footnotes_where_they_are_referenced() {
+        assert_eq!(render("Text[^note].\n\n[^note]: The note.\n"),
+                   "Text\\footnote{The note.}.\n\n");
+        assert_eq!(render("[^first]: Defined first.\n\nText[^first] and more.\n"),
+                   "Text\\footnote{Defined first.} and more.\n\n");
+    }
+}
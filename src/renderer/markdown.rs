@@ -0,0 +1,246 @@
+use renderer::Renderer;
+use book::MDBook;
+use book::bookitem::{BookItem, Chapter};
+use errors::Result;
+use utils;
+use regex::{Regex, Captures};
+
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+/// Renders the whole book as a single markdown file, `book.md`, in the order of `SUMMARY.md`.
+///
+/// The chapters are the same preprocessed markdown the other renderers see. Every chapter starts
+/// with an anchor, links between chapters are rewritten to point to those anchors, and the headings
+/// of nested chapters are demoted by their depth in the book.
+#[derive(Default)]
+pub struct MarkdownRenderer;
+
+impl MarkdownRenderer {
+    pub fn new() -> Self {
+        MarkdownRenderer
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn render(&self, book: &MDBook) -> Result<()> {
+        debug!("[fn]: MarkdownRenderer::render");
+
+        // Anchors for every chapter, by path without extension
+        let mut anchors = HashMap::new();
+        for item in book.iter() {
+            match *item {
+                BookItem::Chapter(ref ch) |
+                BookItem::Affix(ref ch) if !ch.is_draft() => {
+                    anchors.insert(ch.path.with_extension(""), anchor(&ch.path));
+                },
+                _ => {},
+            }
+        }
+
+        let mut output = String::new();
+        render_items(&book.content, 0, &anchors, &mut output);
+
+        info!("[*] Creating book.md ✓");
+        book.write_file("book.md", output.as_bytes())
+    }
+}
+
+fn render_items(items: &[BookItem], depth: usize, anchors: &HashMap<PathBuf, String>, output: &mut String) {
+    for item in items {
+        match *item {
+            BookItem::Chapter(ref ch) |
+            BookItem::Affix(ref ch) => {
+                render_chapter(ch, depth, anchors, output);
+                render_items(&ch.sub_items, depth + 1, anchors, output);
+            },
+            BookItem::Spacer => output.push_str("---\n\n"),
+        }
+    }
+}
+
+fn render_chapter(ch: &Chapter, depth: usize, anchors: &HashMap<PathBuf, String>, output: &mut String) {
+    if ch.is_draft() {
+        return;
+    }
+
+    output.push_str(&format!("<a id=\"{}\"></a>\n\n", anchor(&ch.path)));
+
+    let dir = ch.path.parent().unwrap_or_else(|| Path::new(""));
+    let mut in_code_block = false;
+    // The lines of the current paragraph, a setext heading when the next line underlines it
+    let mut paragraph: Vec<String> = vec![];
+    // Whether a paragraph can start on the next line, and not continue a list item or a quote
+    let mut block_start = true;
+
+    for line in ch.content.lines() {
+        let trimmed = line.trim_start();
+        let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+
+        if in_code_block || fence {
+            in_code_block = in_code_block != fence;
+            block_start = !in_code_block;
+            push_paragraph(&mut paragraph, output);
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        match setext_level(line) {
+            // Written as an ATX heading, to demote it like the others
+            Some(level) if !paragraph.is_empty() && depth > 0 => {
+                let text: Vec<&str> = paragraph.iter().map(|l| l.trim()).collect();
+                let level = ::std::cmp::min(level + depth, 6);
+                output.push_str(&format!("{} {}\n", "#".repeat(level), text.join(" ")));
+                paragraph.clear();
+                block_start = true;
+            },
+            None if is_paragraph_text(line) && (block_start || !paragraph.is_empty()) => {
+                paragraph.push(rewrite_links(line, dir, anchors));
+                block_start = false;
+            },
+            _ => {
+                push_paragraph(&mut paragraph, output);
+                output.push_str(&rewrite_links(&demote_heading(line, depth), dir, anchors));
+                output.push('\n');
+                block_start = trimmed.is_empty() || trimmed.starts_with('#');
+            },
+        }
+    }
+    push_paragraph(&mut paragraph, output);
+
+    output.push_str("\n");
+}
+
+fn push_paragraph(paragraph: &mut Vec<String>, output: &mut String) {
+    for line in paragraph.drain(..) {
+        output.push_str(&line);
+        output.push('\n');
+    }
+}
+
+// The level of the heading a line of `===` (1) or `---` (2) underlines
+fn setext_level(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start().len();
+    let underline = line.trim();
+    if indent > 3 || underline.is_empty() {
+        None
+    } else if underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// Whether `line` can be the text of a paragraph, rather than a heading, a list item, a quote, ...
+fn is_paragraph_text(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let numbered = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    let list_item = ["- ", "* ", "+ "].iter().any(|marker| trimmed.starts_with(marker)) ||
+                    (numbered > 0 && (trimmed[numbered..].starts_with(". ") || trimmed[numbered..].starts_with(") ")));
+
+    !trimmed.is_empty() && indent < 4 && !list_item && !trimmed.starts_with('#') && !trimmed.starts_with('>') &&
+    !trimmed.starts_with('|') && !trimmed.starts_with('<')
+}
+
+// Adds `depth` levels to an ATX heading, headings deeper than 6 are kept at 6. Setext headings are
+// demoted by `render_chapter()`, which knows the line they underline.
+fn demote_heading(line: &str, depth: usize) -> String {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if level == 0 || depth == 0 || !line[level..].starts_with(' ') {
+        return line.to_owned();
+    }
+
+    let new_level = ::std::cmp::min(level + depth, 6);
+    format!("{}{}", "#".repeat(new_level), &line[level..])
+}
+
+// Points links to other chapters of the book to their anchor in the document
+fn rewrite_links(line: &str, dir: &Path, anchors: &HashMap<PathBuf, String>) -> String {
+    lazy_static! {
+        static ref LINK: Regex = Regex::new(r#"\]\(([^)\s#]*)(#[^)\s]*)?(\s+"[^"]*")?\)"#).unwrap();
+    }
+
+    LINK.replace_all(line, |caps: &Captures| {
+        let target = &caps[1];
+        let fragment = caps.get(2).map(|m| m.as_str()).unwrap_or("");
+        let title = caps.get(3).map(|m| m.as_str()).unwrap_or("");
+
+        if target.is_empty() || target.contains("://") || target.starts_with("mailto:") {
+            return caps[0].to_owned();
+        }
+
+        let path = utils::fs::normalize_path(&dir.join(target)).with_extension("");
+        match anchors.get(&path) {
+            // Links to a heading keep their fragment, the heading is in this document too
+            Some(_) if !fragment.is_empty() => format!("]({}{})", fragment, title),
+            Some(anchor) => format!("](#{}{})", anchor, title),
+            None => caps[0].to_owned(),
+        }
+    }).into_owned()
+}
+
+// A unique anchor for a chapter, derived from its path
+fn anchor(path: &Path) -> String {
+    path.with_extension("")
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{demote_heading, rewrite_links, render_chapter, anchor};
+    use book::bookitem::Chapter;
+    use std::path::{Path, PathBuf};
+    use std::collections::HashMap;
+
+    #[test]
+    fn it_demotes_headings_by_depth() {
+        assert_eq!(demote_heading("# Title", 0), "# Title");
+        assert_eq!(demote_heading("# Title", 2), "### Title");
+        assert_eq!(demote_heading("##### Title", 3), "###### Title");
+        assert_eq!(demote_heading("#hashtag", 1), "#hashtag");
+        assert_eq!(demote_heading("Some text", 1), "Some text");
+    }
+
+    #[test]
+    fn it_rewrites_links_between_chapters() {
+        let mut anchors = HashMap::new();
+        anchors.insert(PathBuf::from("format/config"), anchor(Path::new("format/config")));
+
+        let dir = Path::new("cli");
+        assert_eq!(rewrite_links("See [the config](../format/config.md).", dir, &anchors),
+                   "See [the config](#format-config).");
+        assert_eq!(rewrite_links("See [dest](../format/config#dest).", dir, &anchors),
+                   "See [dest](#dest).");
+        assert_eq!(rewrite_links("An [external](https://example.com) link", dir, &anchors),
+                   "An [external](https://example.com) link");
+        assert_eq!(rewrite_links("A [missing](missing.md) chapter", dir, &anchors),
+                   "A [missing](missing.md) chapter");
+    }
+
+    #[test]
+    fn it_demotes_setext_headings() {
+        let mut ch = Chapter::new("Nested".to_owned(), PathBuf::from("nested.md"));
+        ch.content = "Title\n=====\n\nSome\ntext\n---\n\n- Item\n---\n\n```\ncode\n```\n---\n".to_owned();
+
+        let mut output = String::new();
+        render_chapter(&ch, 1, &HashMap::new(), &mut output);
+        assert_eq!(output,
+                   "<a id=\"nested\"></a>\n\n## Title\n\n### Some text\n\n- Item\n---\n\n```\ncode\n```\n---\n\n");
+
+        output.clear();
+        render_chapter(&ch, 0, &HashMap::new(), &mut output);
+        assert_eq!(output, format!("<a id=\"nested\"></a>\n\n{}\n", ch.content));
+    }
+}
@@ -1,9 +1,21 @@
 pub use self::html_handlebars::HtmlHandlebars;
+pub use self::cmd::CmdRenderer;
+pub use self::epub::EpubRenderer;
+pub use self::markdown::MarkdownRenderer;
+pub use self::latex::LatexRenderer;
 
 mod html_handlebars;
+mod cmd;
+mod epub;
+mod markdown;
+mod latex;
 
-use std::error::Error;
+use errors::Result;
 
 pub trait Renderer {
-    fn render(&self, book: &::book::MDBook) -> Result<(), Box<Error>>;
+    /// The name of the renderer, as used in `book.toml` and by preprocessors to decide whether
+    /// they support it
+    fn name(&self) -> &str;
+
+    fn render(&self, book: &::book::MDBook) -> Result<()>;
 }
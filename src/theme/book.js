@@ -138,6 +138,18 @@ $( document ).ready(function() {
 
         var code_block = $(this);
         var pre_block = $(this).parent();
+
+        if(code_block.hasClass("editable")) {
+            // Editable code is shown in full, so that any line can be changed. The highlighting
+            // would go stale while editing, the plain text is kept instead.
+            var text = code_block.text().split("\n").map(function(line) {
+                return line.replace(/^(\s*)#(\s|$)/, "$1");
+            }).join("\n");
+            code_block.text(text);
+            code_block.attr("contenteditable", "true").attr("spellcheck", "false");
+            return;
+        }
+
         // hide lines
         var lines = code_block.html().split("\n");
         var first_non_hidden_line = false;
@@ -198,6 +210,12 @@ $( document ).ready(function() {
     });
 
 
+    // Search, if the index was generated
+    var searchbar = $("#searchbar");
+    if (searchbar.length && typeof window.searchindex !== 'undefined') {
+        init_search(window.searchindex, searchbar);
+    }
+
 });
 
 
@@ -208,7 +226,9 @@ function run_rust_code(code_block) {
         result_block = code_block.find(".result");
     }
 
-    let text = code_block.find(".language-rust").text();
+    var code = code_block.find(".language-rust");
+    // innerText keeps the line breaks typed in editable blocks
+    let text = code.hasClass("editable") ? code[0].innerText : code.text();
 
     let params = {
         version: "stable",
@@ -223,7 +243,8 @@ function run_rust_code(code_block) {
     result_block.text("Running...");
 
     $.ajax({
-        url: "https://play.rust-lang.org/evaluate.json",
+        // Set by the page from `[output.html.playpen] url`, custom themes may not define it
+        url: typeof playpen_url !== "undefined" ? playpen_url : "https://play.rust-lang.org/evaluate.json",
         method: "POST",
         crossDomain: true,
         dataType: "json",
@@ -231,6 +252,91 @@ function run_rust_code(code_block) {
         data: JSON.stringify(params),
         success: function(response){
             result_block.text(response.result);
+        },
+        error: function(xhr, status, error){
+            result_block.text("Could not run the code: " + (error || status));
+        }
+    });
+}
+
+
+function init_search(index, searchbar) {
+    var wrapper = $("#search-wrapper");
+    var results = $("#searchresults");
+    var results_outer = $("#searchresults-outer");
+    var results_header = $("#searchresults-header");
+    var words = Object.keys(index.index);
+
+    function show_search() {
+        wrapper.removeClass("hidden");
+        searchbar.focus();
+    }
+
+    function hide_search() {
+        wrapper.addClass("hidden");
+        searchbar.blur();
+    }
+
+    $("#search-toggle").click(function(){
+        if (wrapper.hasClass("hidden")) { show_search(); } else { hide_search(); }
+    });
+
+    // 'S' or '/' opens the search, escape closes it
+    $(document).on('keydown', function (e) {
+        if (e.altKey || e.ctrlKey || e.metaKey) { return; }
+        if ($(e.target).is("input, textarea")) {
+            if (e.keyCode === 27) { hide_search(); }
+            return;
         }
+        if (e.keyCode === 83 || e.keyCode === 191) {
+            e.preventDefault();
+            show_search();
+        }
+    });
+
+    searchbar.on('input', function(){
+        var query = searchbar.val();
+        results.empty();
+
+        if ($.trim(query) === "") {
+            results_outer.addClass("hidden");
+            return;
+        }
+
+        var found = search_index(index, words, query);
+        results_header.text(found.length + " search results for '" + query + "':");
+
+        $.each(found, function(i, doc){
+            var item = $("<li></li>");
+            $("<a></a>").attr("href", doc.url).text(doc.breadcrumbs).appendTo(item);
+            $("<span class=\"teaser\"></span>").text(doc.teaser).appendTo(item);
+            results.append(item);
+        });
+
+        results_outer.removeClass("hidden");
     });
 }
+
+function search_index(index, words, query) {
+    var scores = {};
+    var terms = query.toLowerCase().split(/[^0-9a-z\u00C0-\uFFFF]+/).filter(function(term){
+        return term.length > 1;
+    });
+
+    $.each(terms, function(i, term){
+        $.each(words, function(j, word){
+            if (word.indexOf(term) !== 0) { return; }
+
+            // Exact matches weigh more than prefix matches
+            var weight = (word === term) ? 1 : 0.5;
+            $.each(index.index[word], function(k, posting){
+                scores[posting[0]] = (scores[posting[0]] || 0) + posting[1] * weight;
+            });
+        });
+    });
+
+    return Object.keys(scores)
+        .sort(function(a, b){ return scores[b] - scores[a]; })
+        .slice(0, index.config.limit_results)
+        .map(function(doc){ return index.docs[doc]; });
+}
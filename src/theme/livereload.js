@@ -0,0 +1,176 @@
+// Live reload of the pages served by `mdbook serve`. The server injects this script in every page,
+// after setting `livereload_url` to the address of its websocket, or to its path on the server of
+// the page.
+//
+// After every build, the server sends the files of the book that changed, relative to its root:
+//
+//     {"type": "changed", "files": ["format/config.html", "book.css"]}
+//
+// Stylesheets are replaced without reloading the page. The page reloads when it changed itself or
+// when a script, an image, ... changed, and keeps its scroll positions across the reload.
+//
+// When a build fails, the server sends the error instead, which is shown over the page until the
+// next successful build:
+//
+//     {"type": "error", "message": "...", "causes": [], "file": "src/SUMMARY.md", "line": 3, "column": 1}
+(function () {
+    var STATE_KEY = "mdbook-livereload";
+    var SCROLLED = ["page-wrapper", "sidebar"];
+    var OVERLAY_ID = "livereload-error";
+
+    // The root of the book, the <base href> of the page points to it
+    function rootUrl() {
+        return document.baseURI.split("#")[0].split("?")[0].replace(/[^\/]*$/, "");
+    }
+
+    // Pages without their extension, so that "intro", "intro.html" and "intro.html#usage" are the same
+    function normalize(path) {
+        path = path.split("#")[0].split("?")[0];
+        if (path === "" || /\/$/.test(path)) {
+            path += "index.html";
+        }
+        return path.replace(/\.html$/, "");
+    }
+
+    function currentPage() {
+        var root = rootUrl();
+        var url = location.href;
+        return normalize(url.indexOf(root) === 0 ? url.substr(root.length) : url);
+    }
+
+    function swapStylesheet(file) {
+        var url = rootUrl() + file;
+        var links = document.querySelectorAll('link[rel="stylesheet"]');
+        for (var i = 0; i < links.length; i++) {
+            var href = links[i].href.split("?")[0];
+            if (href === url) {
+                links[i].href = href + "?livereload=" + Date.now();
+            }
+        }
+    }
+
+    function reload() {
+        var state = { page: currentPage(), window: window.pageYOffset, elements: {} };
+        SCROLLED.forEach(function (id) {
+            var element = document.getElementById(id);
+            if (element) {
+                state.elements[id] = element.scrollTop;
+            }
+        });
+        sessionStorage.setItem(STATE_KEY, JSON.stringify(state));
+
+        socket.close();
+        location.reload(true); // force reload from server (not from cache)
+    }
+
+    // Puts the page back where it was before a reload
+    function restore() {
+        var state = sessionStorage.getItem(STATE_KEY);
+        if (state === null) {
+            return;
+        }
+        sessionStorage.removeItem(STATE_KEY);
+
+        state = JSON.parse(state);
+        if (state.page !== currentPage()) {
+            return;
+        }
+
+        window.scrollTo(0, state.window);
+        Object.keys(state.elements).forEach(function (id) {
+            var element = document.getElementById(id);
+            if (element) {
+                element.scrollTop = state.elements[id];
+            }
+        });
+    }
+
+    function hideError() {
+        var overlay = document.getElementById(OVERLAY_ID);
+        if (overlay) {
+            overlay.parentNode.removeChild(overlay);
+        }
+    }
+
+    function showError(error) {
+        hideError();
+
+        var overlay = document.createElement("div");
+        overlay.id = OVERLAY_ID;
+        overlay.title = "Click to hide";
+        overlay.style.cssText = "position: fixed; top: 0; right: 0; bottom: 0; left: 0; z-index: 1000;" +
+            "overflow: auto; padding: 2em; background: rgba(0, 0, 0, 0.85); color: #e8e8e8;" +
+            "font-family: monospace; cursor: pointer;";
+        overlay.addEventListener("click", hideError);
+
+        var title = document.createElement("h2");
+        title.style.cssText = "margin-top: 0; color: #ff6b6b;";
+        title.textContent = "The book failed to build";
+        overlay.appendChild(title);
+
+        if (error.file !== null) {
+            var position = document.createElement("p");
+            position.style.cssText = "font-weight: bold;";
+            position.textContent = error.file +
+                (error.line !== null ? ":" + error.line : "") +
+                (error.column !== null ? ":" + error.column : "");
+            overlay.appendChild(position);
+        }
+
+        var message = document.createElement("pre");
+        message.style.cssText = "white-space: pre-wrap; background: none; color: inherit;";
+        message.textContent = [error.message].concat(error.causes.map(function (cause) {
+            return "Caused by: " + cause;
+        })).join("\n");
+        overlay.appendChild(message);
+
+        document.body.appendChild(overlay);
+    }
+
+    function websocketUrl() {
+        if (livereload_url.charAt(0) !== "/") {
+            return livereload_url;
+        }
+        return (location.protocol === "https:" ? "wss://" : "ws://") + location.host + livereload_url;
+    }
+
+    var socket = new WebSocket(websocketUrl());
+
+    socket.onmessage = function (event) {
+        var message = JSON.parse(event.data);
+        if (message.type === "error") {
+            showError(message);
+            return;
+        }
+        if (message.type !== "changed") {
+            return;
+        }
+
+        // The build succeeded
+        hideError();
+
+        var page = currentPage();
+        var needsReload = false;
+        message.files.forEach(function (file) {
+            if (/\.css$/.test(file)) {
+                swapStylesheet(file);
+            } else if (/\.html$/.test(file)) {
+                needsReload = needsReload || normalize(file) === page;
+            } else if (!/^searchindex\.js(on)?$/.test(file)) {
+                // The search index changes with every chapter, it is only read when searching
+                needsReload = true;
+            }
+        });
+
+        if (needsReload) {
+            reload();
+        }
+    };
+
+    window.addEventListener("beforeunload", function () {
+        socket.close();
+    });
+
+    // After book.js, which scrolls the sidebar to the current chapter when the document is ready
+    window.addEventListener("load", restore);
+})();
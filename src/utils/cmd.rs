@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+
+use errors::{Error, Result};
+
+/// The `Command` of `cmd`, a program followed by its arguments separated by whitespace, like the
+/// commands of preprocessors and renderers in `book.toml`. `what` names the command in the error
+/// when `cmd` is empty, like `preprocessor 'links'`.
+pub fn command(cmd: &str, what: &str) -> Result<Command> {
+    let mut words = cmd.split_whitespace();
+    let program = words.next()
+                       .ok_or_else(|| format!("The command of {} is empty", what))?;
+
+    let mut command = Command::new(program);
+    command.args(words);
+    Ok(command)
+}
+
+/// Runs `command`, built from `cmd`, with `input` on its standard input and waits for it to exit.
+///
+/// Its standard error is captured for the `Error::Command` returned when it fails, its standard
+/// output is left to the caller to set up, captured in the returned `Output` when piped.
+pub fn run(mut command: Command, cmd: &str, input: &[u8]) -> Result<Output> {
+    let mut child = command.stdin(Stdio::piped())
+                           .stderr(Stdio::piped())
+                           .spawn()
+                           .map_err(|e| Error::io(cmd, e))?;
+
+    {
+        let stdin = child.stdin.as_mut().expect("stdin is piped");
+        if let Err(e) = stdin.write_all(input) {
+            // The program may exit without reading all of its input, the exit status will tell
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(Error::Command {
+            command: cmd.to_owned(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{command, run};
+    use errors::Error;
+    use std::process::Stdio;
+
+    #[test]
+    fn it_reports_empty_commands() {
+        match command("  ", "renderer 'empty'") {
+            Err(Error::Other(message)) => assert_eq!(message, "The command of renderer 'empty' is empty"),
+            other => panic!("Expected an error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_runs_commands_with_their_input() {
+        let mut cmd = command("cat", "test").unwrap();
+        cmd.stdout(Stdio::piped());
+        let output = run(cmd, "cat", b"{\"items\": []}").unwrap();
+        assert_eq!(output.stdout, b"{\"items\": []}");
+
+        match run(command("false", "test").unwrap(), "false", b"") {
+            Err(Error::Command { ref command, .. }) => assert_eq!(command, "false"),
+            other => panic!("Expected a command error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
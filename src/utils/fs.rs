@@ -1,16 +1,21 @@
-use std::path::{Path, Component};
-use std::error::Error;
+use std::env;
+use std::path::{Path, PathBuf, Component};
 use std::io::{self, Read};
 use std::fs::{self, File};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use errors::{Error, Result};
 
 /// Takes a path to a file and try to read the file into a String
 
-pub fn file_to_string(path: &Path) -> Result<String, Box<Error>> {
+pub fn file_to_string(path: &Path) -> Result<String> {
     let mut file = match File::open(path) {
         Ok(f) => f,
         Err(e) => {
             debug!("[*]: Failed to open {:?}", path);
-            return Err(Box::new(e));
+            return Err(Error::io(path, e));
         },
     };
 
@@ -18,7 +23,7 @@ pub fn file_to_string(path: &Path) -> Result<String, Box<Error>> {
 
     if let Err(e) = file.read_to_string(&mut content) {
         debug!("[*]: Failed to read {:?}", path);
-        return Err(Box::new(e));
+        return Err(Error::io(path, e));
     }
 
     Ok(content)
@@ -65,6 +70,26 @@ pub fn path_to_root(path: &Path) -> String {
 }
 
 
+/// Resolves the `.` and `..` components of a relative path, without touching the filesystem.
+///
+/// ```ignore
+/// assert_eq!(normalize_path(Path::new("cli/../format/./config.md")), PathBuf::from("format/config.md"));
+/// ```
+
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            Component::Normal(c) => normalized.push(c),
+            _ => {},
+        }
+    }
+    normalized
+}
+
 
 /// This function creates a file and returns it. But before creating the file it checks every
 /// directory in the path to see if it exists, and if it does not it will be created.
@@ -83,16 +108,36 @@ pub fn create_file(path: &Path) -> io::Result<File> {
     File::create(path)
 }
 
+/// Creates a new, empty directory in the temporary directory of the system, with a name starting
+/// with `prefix`. Removing it is up to the caller.
+pub fn create_temp_dir(prefix: &str) -> Result<PathBuf> {
+    // The process id and the counter tell apart the directories of this process, the time those
+    // left behind by an earlier process with the same id
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+
+    for _ in 0..100 {
+        let count = COUNT.fetch_add(1, Ordering::SeqCst);
+        let dir = env::temp_dir().join(format!("{}-{}-{}-{}", prefix, process::id(), nanos, count));
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Error::io(dir, e)),
+        }
+    }
+    Err(Error::Other(format!("Could not create a new directory in {}", env::temp_dir().display())))
+}
+
 /// Removes all the content of a directory but not the directory itself
 
-pub fn remove_dir_content(dir: &Path) -> Result<(), Box<Error>> {
-    for item in try!(fs::read_dir(dir)) {
+pub fn remove_dir_content(dir: &Path) -> Result<()> {
+    for item in try!(fs::read_dir(dir).map_err(|e| Error::io(dir, e))) {
         if let Ok(item) = item {
             let item = item.path();
             if item.is_dir() {
-                try!(fs::remove_dir_all(item));
+                try!(fs::remove_dir_all(&item).map_err(|e| Error::io(item, e)));
             } else {
-                try!(fs::remove_file(item));
+                try!(fs::remove_file(&item).map_err(|e| Error::io(item, e)));
             }
         }
     }
@@ -104,14 +149,14 @@ pub fn remove_dir_content(dir: &Path) -> Result<(), Box<Error>> {
 /// Copies all files of a directory to another one except the files with the extensions given in the
 /// `ext_blacklist` array
 
-pub fn copy_files_except_ext(from: &Path, to: &Path, recursive: bool, ext_blacklist: &[&str]) -> Result<(), Box<Error>> {
+pub fn copy_files_except_ext(from: &Path, to: &Path, recursive: bool, ext_blacklist: &[&str]) -> Result<()> {
     debug!("[fn] copy_files_except_ext");
     // Check that from and to are different
     if from == to {
         return Ok(());
     }
     debug!("[*] Loop");
-    for entry in fs::read_dir(from)? {
+    for entry in fs::read_dir(from).map_err(|e| Error::io(from, e))? {
         let entry = entry?;
         debug!("[*] {:?}", entry.path());
         let path = entry.path();
@@ -145,7 +190,8 @@ pub fn copy_files_except_ext(from: &Path, to: &Path, recursive: bool, ext_blackl
             info!("[*] Copying file: {:?}\n    to {:?}",
                   &path,
                   &to.join(path.file_name().expect("a file should have a file name...")));
-            fs::copy(&path, &to.join(path.file_name().expect("a file should have a file name...")))?;
+            fs::copy(&path, &to.join(path.file_name().expect("a file should have a file name...")))
+                .map_err(|e| Error::io(&path, e))?;
         }
     }
     Ok(())
@@ -159,14 +205,13 @@ pub fn copy_files_except_ext(from: &Path, to: &Path, recursive: bool, ext_blackl
 
 #[cfg(test)]
 mod tests {
-    extern crate tempdir;
-
-    use super::copy_files_except_ext;
+    use super::{copy_files_except_ext, create_temp_dir};
     use std::fs;
+    use tempdir::TempDir;
 
     #[test]
     fn copy_files_except_ext_test() {
-        let tmp = match tempdir::TempDir::new("") {
+        let tmp = match TempDir::new("") {
             Ok(t) => t,
             Err(_) => panic!("Could not create a temp dir"),
         };
@@ -225,4 +270,17 @@ mod tests {
         }
 
     }
+
+    #[test]
+    fn create_temp_dir_creates_new_empty_dirs() {
+        let first = create_temp_dir("mdbook-fs-test").unwrap();
+        let second = create_temp_dir("mdbook-fs-test").unwrap();
+
+        assert!(first != second);
+        for dir in &[&first, &second] {
+            assert!(dir.file_name().unwrap().to_string_lossy().starts_with("mdbook-fs-test-"));
+            assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+            fs::remove_dir(dir).unwrap();
+        }
+    }
 }
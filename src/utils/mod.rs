@@ -1,26 +1,22 @@
 pub mod fs;
+pub mod cmd;
+#[cfg(test)]
+pub mod testing;
 
-//use pulldown_cmark::{Parser, html, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
-//
-//
-/////
-/////
-///// Wrapper around the pulldown-cmark parser and renderer to render markdown
-//
-//pub fn render_markdown(text: &str) -> String {
-//    let mut s = String::with_capacity(text.len() * 3 / 2);
-//
-//    let mut opts = Options::empty();
-//    opts.insert(OPTION_ENABLE_TABLES);
-//    opts.insert(OPTION_ENABLE_FOOTNOTES);
-//
-//    let p = Parser::new_ext(text, opts);
-//    html::push_html(&mut s, p);
-//    s
-//}
+use pulldown_cmark::{Parser, Options, OPTION_ENABLE_TABLES, OPTION_ENABLE_FOOTNOTES};
+use kramer::ast_pull;
 
 
-use kramer::ast_pull;
+/// The markdown event stream the renderers transform, with tables and footnotes enabled. The html
+/// and LaTeX renderers share it, so that they agree on the markdown they accept.
+
+pub fn markdown_parser(text: &str) -> Parser {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    Parser::new_ext(text, opts)
+}
 
 pub fn render_markdown(text: &str) -> String {
     let cfg = ast_pull::Config::default();
@@ -0,0 +1,31 @@
+//! Fixtures shared by the tests
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use tempdir::TempDir;
+
+/// A temporary directory holding `files`, pairs of a path relative to the directory and a content
+pub fn temp_dir(files: &[(&str, &str)]) -> TempDir {
+    let tmp = TempDir::new("mdbook").unwrap();
+    write_files(tmp.path(), files);
+    tmp
+}
+
+/// A temporary book made of its `book.toml`, its `src/SUMMARY.md` and the other `files`, whose
+/// paths are relative to the root of the book
+pub fn temp_book(config: &str, summary: &str, files: &[(&str, &str)]) -> TempDir {
+    let tmp = temp_dir(&[("book.toml", config), ("src/SUMMARY.md", summary)]);
+    write_files(tmp.path(), files);
+    tmp
+}
+
+/// Writes `files` in `dir`, creating their parent directories
+pub fn write_files(dir: &Path, files: &[(&str, &str)]) {
+    for &(path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+}